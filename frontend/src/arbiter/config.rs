@@ -0,0 +1,390 @@
+use crate::arbiter::tournament::access::Access;
+use crate::arbiter::tournament::adjudication::ScoreRule;
+use crate::arbiter::tournament::rules::{IllegalMovePolicy, Rules};
+use crate::arbiter::tournament::scheduler::Limits;
+use crate::arbiter::tournament::Tournament;
+use chinese_chess::game::Game;
+use chinese_chess::notation;
+use chinese_chess::pgn::Pgn;
+use log::info;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// everything needed to reproduce a run of the arbiter, read from a file such as
+//
+//     bind = 0.0.0.0
+//     port = 6000
+//     http = 8080
+//     secret = open-sesame
+//     move-time = 1000
+//     grace-time = 1000
+//     openings = openings.pgn
+//     pgn = games.pgn
+//
+//     max-games = 4
+//
+//     [player robot]
+//     instances = 2
+//     priority = 1
+//     key = beep-boop
+//
+//     [events]
+//     round-robin robot other --double
+//
+// where the events are console commands run once at startup, and relative paths are relative to the file
+#[derive(Default, Debug)]
+pub struct Config {
+    pub bind: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub http: Option<u16>,
+    pub log_file: Option<PathBuf>,
+    pub games: Option<PathBuf>,
+    pub pgn: Option<PathBuf>,
+    pub openings: Option<PathBuf>,
+    pub move_time: Option<u32>,
+    pub grace_time: Option<u32>,
+    pub illegal_move: Option<IllegalMovePolicy>,
+    pub draw_adjudication: Option<ScoreRule>,
+    pub resign_adjudication: Option<ScoreRule>,
+    pub max_plies: Option<u32>,
+    pub max_games: Option<u32>,
+    pub max_player_games: Option<u32>,
+    pub secret: Option<String>,
+    pub players: Vec<(String, u32)>, // names with the number of instances expected to connect
+    pub priorities: Vec<(String, i32)>,
+    pub keys: Vec<(String, String)>,
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String,
+}
+
+enum Section {
+    Settings,
+    Player(usize),
+    Events,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|error| format!("failed to read '{}': {error}", path.display()))?;
+        let mut config = text
+            .parse::<Config>()
+            .map_err(|error| format!("invalid config '{}': {error}", path.display()))?;
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let paths = [
+            &mut config.log_file,
+            &mut config.games,
+            &mut config.pgn,
+            &mut config.openings,
+        ];
+        for path in paths.into_iter().flatten() {
+            *path = directory.join(&path);
+        }
+
+        Ok(config)
+    }
+
+    // the config with every setting given in the other one replacing its own, such as flags given on the command line
+    // taking precedence over the file, with the players, keys and events of both
+    pub fn overridden(mut self, other: &Config) -> Self {
+        self.bind = other.bind.or(self.bind);
+        self.port = other.port.or(self.port);
+        self.http = other.http.or(self.http);
+        self.log_file = other.log_file.clone().or(self.log_file);
+        self.games = other.games.clone().or(self.games);
+        self.pgn = other.pgn.clone().or(self.pgn);
+        self.openings = other.openings.clone().or(self.openings);
+        self.move_time = other.move_time.or(self.move_time);
+        self.grace_time = other.grace_time.or(self.grace_time);
+        self.illegal_move = other.illegal_move.or(self.illegal_move);
+        self.draw_adjudication = other.draw_adjudication.or(self.draw_adjudication);
+        self.resign_adjudication = other.resign_adjudication.or(self.resign_adjudication);
+        self.max_plies = other.max_plies.or(self.max_plies);
+        self.max_games = other.max_games.or(self.max_games);
+        self.max_player_games = other.max_player_games.or(self.max_player_games);
+        self.secret = other.secret.clone().or(self.secret);
+        self.players.extend(other.players.iter().cloned());
+        self.priorities.extend(other.priorities.iter().cloned());
+        self.keys.extend(other.keys.iter().cloned());
+        self.events.extend(other.events.iter().cloned());
+        self
+    }
+
+    // the rules with every setting in the config replacing the one given
+    pub fn rules(&self, mut rules: Rules) -> Rules {
+        rules.move_time = self.move_time.unwrap_or(rules.move_time);
+        rules.grace_time = self.grace_time.or(rules.grace_time);
+        rules.illegal_move = self.illegal_move.unwrap_or(rules.illegal_move);
+
+        let adjudication = &mut rules.adjudication;
+        adjudication.draw = self.draw_adjudication.or(adjudication.draw);
+        adjudication.resign = self.resign_adjudication.or(adjudication.resign);
+        adjudication.max_plies = self.max_plies.or(adjudication.max_plies);
+        rules
+    }
+
+    // applies the settings that can change while the arbiter is running, which excludes the address, ports, log
+    // file, game records and events
+    pub fn apply(&self, tournament: &mut Tournament) -> Result<(), String> {
+        tournament.set_rules(self.rules(tournament.rules()));
+        info!("applied rules {:?}", tournament.rules());
+
+        if let Some(path) = &self.openings {
+            tournament.set_openings(load_openings(path)?);
+        }
+
+        if let Some(path) = &self.pgn {
+            tournament.export(path);
+        }
+
+        for (name, instances) in &self.players {
+            tournament.expect(name, *instances);
+        }
+
+        for (name, priority) in &self.priorities {
+            tournament.prioritize(name, *priority);
+        }
+
+        if self.max_games.is_some() || self.max_player_games.is_some() {
+            tournament.set_limits(self.limits(tournament.limits()));
+        }
+
+        if self.secret.is_some() || !self.keys.is_empty() {
+            tournament.set_access(self.access(tournament.access().clone()));
+        }
+
+        Ok(())
+    }
+
+    // the limits with every setting in the config replacing the one given
+    pub fn limits(&self, limits: Limits) -> Limits {
+        Limits {
+            games: self.max_games.or(limits.games),
+            player_games: self.max_player_games.or(limits.player_games),
+        }
+    }
+
+    // the access with the secret in the config replacing the one given and the keys in the config added to it
+    pub fn access(&self, mut access: Access) -> Access {
+        access.secret = self.secret.clone().or(access.secret);
+        access.keys.extend(self.keys.iter().cloned());
+        access
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| format!("invalid value '{value}'"))
+        }
+
+        match key {
+            "bind" => self.bind = Some(parse(value)?),
+            "port" => self.port = Some(parse(value)?),
+            "http" => self.http = Some(parse(value)?),
+            "log-file" => self.log_file = Some(value.into()),
+            "games" => self.games = Some(value.into()),
+            "pgn" => self.pgn = Some(value.into()),
+            "openings" => self.openings = Some(value.into()),
+            "move-time" => self.move_time = Some(parse(value)?),
+            "grace-time" => self.grace_time = Some(parse(value)?),
+            "illegal-move" => self.illegal_move = Some(value.parse()?),
+            "draw-adjudication" => self.draw_adjudication = Some(value.parse()?),
+            "resign-adjudication" => self.resign_adjudication = Some(value.parse()?),
+            "max-plies" => self.max_plies = Some(parse(value)?),
+            "max-games" => self.max_games = Some(parse(value)?),
+            "max-player-games" => self.max_player_games = Some(parse(value)?),
+            "secret" => self.secret = Some(parse_key(value)?),
+            _ => return Err(format!("unknown setting '{key}'")),
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        let mut section = Section::Settings;
+
+        for (index, line) in s.lines().enumerate() {
+            let error = |message: String| ConfigError {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                let mut words = header.split_whitespace();
+                section = match (words.next(), words.next(), words.next()) {
+                    (Some("player"), Some(name), None) => {
+                        config.players.push((name.to_owned(), 1));
+                        Section::Player(config.players.len() - 1)
+                    }
+                    (Some("events"), None, None) => Section::Events,
+                    _ => return Err(error(format!("unknown section '{header}'"))),
+                };
+                continue;
+            }
+
+            if let Section::Events = section {
+                config.events.push(line.to_owned());
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected <key> = <value> but found '{line}'")));
+            };
+
+            let (key, value) = (key.trim(), value.trim());
+            match section {
+                Section::Settings => config.set(key, value).map_err(error)?,
+                Section::Player(index) if key == "instances" => match value.parse() {
+                    Ok(instances) => config.players[index].1 = instances,
+                    Err(_) => return Err(error(format!("invalid instance count '{value}'"))),
+                },
+                Section::Player(index) if key == "priority" => match value.parse() {
+                    Ok(priority) => config.priorities.push((config.players[index].0.clone(), priority)),
+                    Err(_) => return Err(error(format!("invalid priority '{value}'"))),
+                },
+                Section::Player(index) if key == "key" => {
+                    let key = parse_key(value).map_err(error)?;
+                    config.keys.push((config.players[index].0.clone(), key));
+                }
+                Section::Player(_) => return Err(error(format!("unknown player setting '{key}'"))),
+                Section::Events => unreachable!(),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// keys are sent as a single word in the handshake
+fn parse_key(value: &str) -> Result<String, String> {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return Err(format!("invalid key '{value}', which must be a single word"));
+    }
+    Ok(value.to_owned())
+}
+
+// reads the positions at the end of every game in a pgn file, or one fen per line from any other file
+pub fn load_openings(path: &Path) -> Result<Vec<Game>, String> {
+    let invalid = |error: &dyn Display| format!("invalid openings '{}': {error}", path.display());
+    let text = std::fs::read_to_string(path).map_err(|error| invalid(&error))?;
+
+    let openings = if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pgn"))
+    {
+        let games = Pgn::parse_all(&text).map_err(|error| invalid(&error))?;
+        games
+            .iter()
+            .map(|pgn| Game::new(pgn.game().board().clone(), pgn.game().red_turn()))
+            .collect::<Vec<_>>()
+    } else {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| notation::parse_fen(line).ok_or_else(|| invalid(&format!("bad fen '{line}'"))))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    if openings.is_empty() {
+        return Err(invalid(&"no openings found"));
+    }
+    Ok(openings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let text = "# overnight run\n\
+            port = 6001\n\
+            move-time = 500 # half a second\n\
+            grace-time = 1000\n\
+            illegal-move = forfeit\n\
+            draw-adjudication = 10:20\n\
+            \n\
+            max-games = 3\n\
+            bind = 0.0.0.0\n\
+            secret = open-sesame\n\
+            [player robot]\n\
+            instances = 2\n\
+            priority = -1\n\
+            key = beep\n\
+            [player other]\n\
+            [events]\n\
+            round-robin robot other --double\n";
+
+        let config = text.parse::<Config>().unwrap();
+        assert_eq!(config.port, Some(6001));
+        assert_eq!(config.players, vec![("robot".to_owned(), 2), ("other".to_owned(), 1)]);
+        assert_eq!(config.events, vec!["round-robin robot other --double"]);
+        assert_eq!(config.priorities, vec![("robot".to_owned(), -1)]);
+        assert_eq!(config.limits(Limits::default()).games, Some(3));
+        assert_eq!(config.bind, Some(IpAddr::from([0, 0, 0, 0])));
+
+        let access = config.access(Access::default());
+        assert_eq!(access.secret.as_deref(), Some("open-sesame"));
+        assert_eq!(access.keys.get("robot").map(String::as_str), Some("beep"));
+
+        let rules = config.rules(Rules::new());
+        assert_eq!(rules.move_time, 500);
+        assert_eq!(rules.grace_time, Some(1000));
+        assert_eq!(rules.illegal_move, IllegalMovePolicy::Forfeit);
+        assert!(rules.adjudication.draw.is_some());
+        assert!(rules.adjudication.resign.is_none());
+    }
+
+    #[test]
+    fn overridden() {
+        let file = "bind = 0.0.0.0\nport = 6001\nmove-time = 500\nmax-games = 3\n[events]\npause\n";
+        let file = file.parse::<Config>().unwrap();
+        let flags = Config {
+            bind: Some(IpAddr::from([127, 0, 0, 1])),
+            move_time: Some(1000),
+            events: vec!["resume".to_owned()],
+            ..Config::default()
+        };
+
+        let config = file.overridden(&flags);
+        assert_eq!(config.bind, Some(IpAddr::from([127, 0, 0, 1])));
+        assert_eq!(config.port, Some(6001));
+        assert_eq!(config.move_time, Some(1000));
+        assert_eq!(config.max_games, Some(3));
+        assert_eq!(config.events, vec!["pause", "resume"]);
+    }
+
+    #[test]
+    fn invalid() {
+        let line = |text: &str| text.parse::<Config>().unwrap_err().line;
+        assert_eq!(line("port = 6000\nport = many"), 2);
+        assert_eq!(line("\n\ncolor = red"), 3);
+        assert_eq!(line("[player]"), 1);
+        assert_eq!(line("[player robot]\ninstances = 2\ndepth = 3"), 3);
+        assert_eq!(line("move-time 100"), 1);
+        assert_eq!(line("[player robot]\nkey = beep boop"), 2);
+    }
+}
@@ -1,13 +1,101 @@
+use crate::arbiter::config::Config;
+use crate::arbiter::tournament::statistics::{self, Sprt};
 use crate::arbiter::tournament::Tournament;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
     #[command(about = "show status of players")]
     Status { names: Vec<String> },
+    #[command(about = "cancel matches of a player that have not started yet")]
+    Dequeue {
+        name: String,
+        #[arg(help = "opponents to cancel matches against, or all if unspecified")]
+        against: Vec<String>,
+    },
+    #[command(about = "end running games without a result")]
+    Abort {
+        #[arg(required = true, help = "ids of the games as shown by list games")]
+        games: Vec<u32>,
+    },
+    #[command(about = "disconnect all instances of a player, or a single instance such as robot:0")]
+    Kick { names: Vec<String> },
+    #[command(about = "stop starting new games")]
+    Pause,
+    #[command(about = "start new games again after pausing")]
+    Resume,
+    #[command(about = "limit how many games run at once, or show the limits if none are given")]
+    Limit {
+        #[arg(long, help = "most games running in total, 0 for unlimited")]
+        games: Option<u32>,
+        #[arg(long, help = "most games running for any single player, 0 for unlimited")]
+        player_games: Option<u32>,
+    },
+    #[command(
+        about = "start the games of a player before those of players with lower priority, which is 0 by default"
+    )]
+    Priority {
+        name: String,
+        #[arg(allow_negative_numbers = true)]
+        priority: i32,
+    },
+    #[command(about = "list running games or queued matches")]
+    List { what: Listing },
+    #[command(
+        alias = "reset",
+        about = "forget the finished games of players, or of everyone if unspecified, for status, standings and ratings \
+                 until the arbiter restarts, keeping the stored records"
+    )]
+    ResetStatus { names: Vec<String> },
+    #[command(about = "apply the settings of the config file again, without running its events")]
+    Reload,
+    #[command(about = "play games between two players until a sequential probability ratio test decides")]
+    Sprt {
+        name: String,
+        #[arg(help = "player to test against")]
+        against: String,
+        #[arg(long, default_value_t = 0.0, help = "elo difference of the null hypothesis")]
+        elo0: f64,
+        #[arg(long, default_value_t = 5.0, help = "elo difference of the alternative hypothesis")]
+        elo1: f64,
+        #[arg(long, default_value_t = 0.05, help = "chance of wrongly accepting elo1")]
+        alpha: f64,
+        #[arg(long, default_value_t = 0.05, help = "chance of wrongly accepting elo0")]
+        beta: f64,
+    },
+    #[command(about = "play every player against every other", alias = "rr")]
+    RoundRobin {
+        names: Vec<String>,
+        #[arg(short, long, help = "whether to play each pairing twice with colors reversed")]
+        double: bool,
+    },
+    #[command(about = "play a player against each of its opponents")]
+    Gauntlet {
+        name: String,
+        #[arg(help = "players to compete against")]
+        opponents: Vec<String>,
+        #[arg(
+            short,
+            long,
+            default_value_t = 2,
+            help = "how many games to play against each opponent"
+        )]
+        count: u32,
+    },
+    #[command(about = "play rounds that pair players with similar points")]
+    Swiss {
+        names: Vec<String>,
+        #[arg(short, long, help = "how many rounds to play")]
+        rounds: u32,
+    },
+    #[command(about = "show the standings of players, or of the most recent event if unspecified")]
+    Standings { names: Vec<String> },
+    #[command(about = "show the rating list of all players")]
+    Ratings,
     #[command(about = "enqueue a player to compete", alias = "e")]
     Enqueue {
         name: String,
@@ -25,6 +113,12 @@ pub enum Command {
     },
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Listing {
+    Games,
+    Queue,
+}
+
 #[derive(Parser)]
 #[command(
     name = "",
@@ -39,10 +133,15 @@ struct Input<T: clap::FromArgMatches + clap::Subcommand> {
     command: T,
 }
 
-pub fn begin(tournament: Arc<RwLock<Tournament>>, exec: &[String], mut console: DefaultEditor) {
+pub fn begin(
+    tournament: Arc<RwLock<Tournament>>,
+    exec: &[String],
+    config: Option<(PathBuf, Config)>, // the config file along with the settings that take precedence over it
+    mut console: DefaultEditor,
+) {
     for command in exec {
         match Input::<Command>::try_parse_from(command.split_whitespace()) {
-            Ok(Input { command }) => execute_command(&tournament, command),
+            Ok(Input { command }) => execute_command(&tournament, config.as_ref(), command),
             Err(error) => {
                 println!("Error parsing exec command '{}': {}", command, error);
             }
@@ -51,18 +150,27 @@ pub fn begin(tournament: Arc<RwLock<Tournament>>, exec: &[String], mut console:
 
     loop {
         let command = read_input::<Command>(&mut console);
-        execute_command(&tournament, command);
+        execute_command(&tournament, config.as_ref(), command);
     }
 }
 
-fn execute_command(tournament: &Arc<RwLock<Tournament>>, command: Command) {
+fn execute_command(tournament: &Arc<RwLock<Tournament>>, config: Option<&(PathBuf, Config)>, command: Command) {
     match command {
         Command::Status { names } => {
             if names.is_empty() {
                 println!("connected players:");
-                for name in tournament.read().unwrap().iter_players() {
+                let tournament = tournament.read().unwrap();
+                for name in tournament.iter_players() {
                     println!("{}", name);
                 }
+
+                for (name, against, sprt, score) in tournament.iter_tests() {
+                    let (lower, upper) = sprt.bounds();
+                    println!(
+                        "sprt {name} vs. {against} - {score} llr {:.3} [{lower:.3}, {upper:.3}]",
+                        sprt.llr(&score)
+                    );
+                }
             } else {
                 for name in names {
                     let tournament = tournament.read().unwrap();
@@ -73,10 +181,148 @@ fn execute_command(tournament: &Arc<RwLock<Tournament>>, command: Command) {
 
                     for (other, status) in status {
                         println!("{name} vs. {other} - {status}");
+                        for (reason, score) in status.iter_reasons() {
+                            println!("    by {reason}: {score}");
+                        }
+
+                        let los = statistics::los(&status.score) * 100.0;
+                        match statistics::trinomial(&status.score) {
+                            Some(estimate) => println!("    elo: {estimate}, los: {los:.1}%"),
+                            None => println!("    elo: n/a, los: {los:.1}%"),
+                        }
+
+                        let pairs = status.pentanomial();
+                        if pairs.iter().sum::<u32>() > 0 {
+                            match statistics::pentanomial(&pairs) {
+                                Some(estimate) => println!("    pentanomial: {pairs:?} elo: {estimate}"),
+                                None => println!("    pentanomial: {pairs:?} elo: n/a"),
+                            }
+                        }
                     }
                 }
             }
         }
+        Command::Dequeue { name, against } => match tournament.write().unwrap().dequeue(&name, &against) {
+            Some(count) => println!("dequeued {count} matches"),
+            None => println!("unknown player '{name}'"),
+        },
+        Command::Abort { games } => {
+            let tournament = tournament.read().unwrap();
+            for id in games {
+                if !tournament.abort(id) {
+                    println!("no running game #{id}");
+                }
+            }
+        }
+        Command::Kick { names } => {
+            let tournament = tournament.read().unwrap();
+            for name in names {
+                let count = tournament.kick(&name);
+                println!("kicked {count} instances of '{name}'");
+            }
+        }
+        Command::Limit { games, player_games } => {
+            let mut tournament = tournament.write().unwrap();
+            let mut limits = tournament.limits();
+            if games.is_none() && player_games.is_none() {
+                println!("running at most {limits}");
+                return;
+            }
+
+            let limit = |limit: u32| (limit > 0).then_some(limit);
+            limits.games = games.map_or(limits.games, limit);
+            limits.player_games = player_games.map_or(limits.player_games, limit);
+            tournament.set_limits(limits);
+        }
+        Command::Priority { name, priority } => tournament.write().unwrap().prioritize(&name, priority),
+        Command::Pause => tournament.write().unwrap().pause(),
+        Command::Resume => tournament.write().unwrap().resume(),
+        Command::List { what: Listing::Games } => {
+            for game in tournament.read().unwrap().iter_games() {
+                println!("{game}");
+            }
+        }
+        Command::List { what: Listing::Queue } => {
+            for (red, black, count) in tournament.read().unwrap().iter_queued() {
+                println!("{red} vs. {black} - {count} queued");
+            }
+        }
+        Command::ResetStatus { names } => tournament.write().unwrap().reset_status(&names),
+        Command::Reload => {
+            let Some((path, overrides)) = config else {
+                println!("no config file was given on startup");
+                return;
+            };
+
+            let result = Config::load(path)
+                .and_then(|config| config.overridden(overrides).apply(&mut tournament.write().unwrap()));
+            match result {
+                Ok(()) => println!("reloaded config '{}'", path.display()),
+                Err(error) => println!("{error}"),
+            }
+        }
+        Command::Sprt {
+            name,
+            against,
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        } => {
+            let sprt = Sprt {
+                elo0,
+                elo1,
+                alpha,
+                beta,
+            };
+
+            let probability = |value: f64| value > 0.0 && value < 1.0;
+            if elo0 >= elo1 || !probability(alpha) || !probability(beta) {
+                println!("invalid sprt bounds, expected elo0 < elo1 and alpha and beta between 0 and 1");
+            } else if let Err(error) = tournament.write().unwrap().sprt(&name, &against, sprt) {
+                println!("{error}");
+            }
+        }
+        Command::RoundRobin { names, double } => {
+            tournament.write().unwrap().round_robin(&names, double);
+        }
+        Command::Gauntlet { name, opponents, count } => {
+            tournament.write().unwrap().gauntlet(&name, &opponents, count);
+        }
+        Command::Swiss { names, rounds } => {
+            if let Err(error) = tournament.write().unwrap().swiss(&names, rounds) {
+                println!("{error}");
+            }
+        }
+        Command::Standings { names } => {
+            let standings = tournament.read().unwrap().standings(&names);
+            println!("rank name games points sb h2h");
+            for (rank, (name, standing)) in standings.into_iter().enumerate() {
+                println!(
+                    "{:>4} {name} {} {:.1} {:.2} {:.1}",
+                    rank + 1,
+                    standing.games,
+                    standing.points,
+                    standing.sonneborn_berger,
+                    standing.head_to_head
+                );
+            }
+        }
+        Command::Ratings => {
+            let ratings = tournament.read().unwrap().ratings();
+            println!("rank name elo error games score");
+            for (rank, (name, rating, score)) in ratings.into_iter().enumerate() {
+                let error =
+                    statistics::trinomial(&score).map_or("n/a".to_owned(), |estimate| format!("{:.1}", estimate.error));
+                let points = score.win as f64 + score.draw as f64 / 2.0;
+                let percentage = points * 100.0 / score.total().max(1) as f64;
+                println!(
+                    "{:>4} {name} {rating:+.1} {error} {} {percentage:.1}%",
+                    rank + 1,
+                    score.total()
+                );
+            }
+        }
         Command::Enqueue {
             name,
             against,
@@ -0,0 +1,329 @@
+use crate::arbiter::json::Json;
+use crate::arbiter::tournament::Tournament;
+use crate::line_stream::MAX_LENGTH;
+use crate::protocol::Analysis;
+use log::{debug, info};
+use smol::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use smol::net::{TcpListener, TcpStream};
+use smol::Timer;
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+const DASHBOARD: &str = include_str!("dashboard.html");
+const MAX_HEADERS: usize = 100;
+const REQUEST_TIME: Duration = Duration::from_secs(10); // how long a connection has to send its whole request
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+// serves the state of the tournament as json along with a dashboard page that renders it, meant for monitoring from
+// a browser on the same machine rather than for controlling the tournament
+pub async fn serve(tournament: Arc<RwLock<Tournament>>, address: SocketAddr) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    info!("dashboard available at http://{address}/");
+
+    loop {
+        let (stream, address) = listener.accept().await?;
+        let tournament = tournament.clone();
+        smol::spawn(async move {
+            if let Err(error) = handle(&tournament, stream).await {
+                debug!("http connection from {address} closed with error {error}");
+            }
+        })
+        .detach();
+    }
+}
+
+async fn handle(tournament: &RwLock<Tournament>, mut stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.clone());
+    let timeout = async {
+        Timer::after(REQUEST_TIME).await;
+        Err(Error::new(ErrorKind::TimedOut, "timed out reading the request"))
+    };
+    let request = smol::future::or(read_request(&mut reader), timeout).await?;
+    let response = answer(&tournament.read().unwrap(), &request);
+
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.flush().await
+}
+
+// the request line, after reading the headers that follow it as they are never needed but have to be read before
+// replying
+async fn read_request(reader: &mut BufReader<TcpStream>) -> std::io::Result<String> {
+    let mut request = String::new();
+    read_line(reader, &mut request).await?;
+
+    for _ in 0..MAX_HEADERS {
+        let mut header = String::new();
+        if read_line(reader, &mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    Ok(request)
+}
+
+// reads a line no longer than the line streams accept, so that a single connection cannot grow the buffer without limit
+async fn read_line(reader: &mut BufReader<TcpStream>, line: &mut String) -> std::io::Result<usize> {
+    let length = reader.take(MAX_LENGTH as u64 + 1).read_line(line).await?;
+    if length > MAX_LENGTH && !line.ends_with('\n') {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("line longer than {MAX_LENGTH} bytes"),
+        ));
+    }
+    Ok(length)
+}
+
+fn answer(tournament: &Tournament, request: &str) -> Response {
+    let mut parts = request.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => respond(tournament, target),
+        (Some(_), Some(_)) => error("405 Method Not Allowed", "only GET is supported"),
+        _ => error("400 Bad Request", "malformed request"),
+    }
+}
+
+fn respond(tournament: &Tournament, target: &str) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let parameter = |key: &str| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+    };
+
+    let json = match path {
+        "/" | "/index.html" => {
+            return Response {
+                status: "200 OK",
+                content_type: "text/html; charset=utf-8",
+                body: DASHBOARD.to_owned(),
+            };
+        }
+        "/api/players" => players(tournament),
+        "/api/instances" => instances(tournament),
+        "/api/standings" => standings(tournament),
+        "/api/games" => games(tournament),
+        "/api/records" => {
+            // either a page starting from an index or the most recent games
+            let number = |key| parameter(key).map(str::parse::<usize>).transpose();
+            match (number("from"), number("last")) {
+                (Ok(from), Ok(None)) => records(tournament, from.unwrap_or(0), usize::MAX),
+                (Ok(None), Ok(Some(last))) => records(tournament, tournament.record_count().saturating_sub(last), last),
+                _ => return error("400 Bad Request", "expected either from or last as a number"),
+            }
+        }
+        _ => return error("404 Not Found", "unknown path"),
+    };
+
+    Response {
+        status: "200 OK",
+        content_type: "application/json",
+        body: json.to_string(),
+    }
+}
+
+fn error(status: &'static str, message: &str) -> Response {
+    Response {
+        status,
+        content_type: "application/json",
+        body: Json::object([("error", message.into())]).to_string(),
+    }
+}
+
+// every player with its rating and totals, from best to worst
+fn players(tournament: &Tournament) -> Json {
+    let players = tournament.ratings().into_iter().map(|(name, rating, score)| {
+        let statuses = tournament.status(&name).into_iter().flatten();
+        let (queued, running) = statuses.fold((0, 0), |(queued, running), (_, status)| {
+            (queued + status.queued, running + status.running)
+        });
+
+        Json::object([
+            ("name", name.into()),
+            ("rating", rating.into()),
+            ("win", score.win.into()),
+            ("loss", score.loss.into()),
+            ("draw", score.draw.into()),
+            ("queued", queued.into()),
+            ("running", running.into()),
+        ])
+    });
+    Json::Array(players.collect())
+}
+
+// every connected instance, either idle or playing in a running game
+fn instances(tournament: &Tournament) -> Json {
+    let instances = tournament.instances().into_iter().map(|(player, name, game)| {
+        Json::object([("name", name.into()), ("player", player.into()), ("game", game.into())])
+    });
+    Json::Array(instances.collect())
+}
+
+fn standings(tournament: &Tournament) -> Json {
+    let standings = tournament.standings(&[]).into_iter().map(|(name, standing)| {
+        Json::object([
+            ("name", name.into()),
+            ("games", standing.games.into()),
+            ("points", standing.points.into()),
+            ("sonneborn_berger", standing.sonneborn_berger.into()),
+            ("head_to_head", standing.head_to_head.into()),
+        ])
+    });
+    Json::Array(standings.collect())
+}
+
+fn games(tournament: &Tournament) -> Json {
+    let games = tournament.iter_games().map(|game| {
+        let progress = game.progress();
+        let position = game.position();
+        let [red_used, black_used] = progress.used.map(|used| used.as_millis() as f64);
+        let last_move = position.history().last().map(|(mv, _)| mv.to_string());
+        let thinking = progress.thinking.map(|(red, since)| {
+            Json::object([
+                ("red", red.into()),
+                ("for", (since.elapsed().as_millis() as f64).into()),
+            ])
+        });
+
+        let analysis = game.analysis().map(|(red, analysis)| {
+            let mut fields = vec![("red", red.into())];
+            fields.extend(analysis_fields(&analysis));
+            Json::Object(fields)
+        });
+
+        Json::object([
+            ("id", game.id.into()),
+            ("red", game.red.1.as_str().into()),
+            ("black", game.black.1.as_str().into()),
+            ("plies", progress.plies.into()),
+            ("fen", position.board().fen().into()),
+            ("red_turn", position.red_turn().into()),
+            ("last_move", last_move.into()),
+            ("red_used", red_used.into()),
+            ("black_used", black_used.into()),
+            ("thinking", thinking.unwrap_or(Json::Null)),
+            ("analysis", analysis.unwrap_or(Json::Null)),
+        ])
+    });
+    Json::Array(games.collect())
+}
+
+fn analysis_fields(analysis: &Analysis) -> Vec<(&'static str, Json)> {
+    let pv = analysis.pv.iter().map(ToString::to_string).collect::<Vec<_>>();
+    vec![
+        ("depth", analysis.depth.into()),
+        ("score", (analysis.score as f64).into()),
+        ("nodes", analysis.nodes.map(|nodes| nodes as f64).into()),
+        ("nps", analysis.nps.map(|nps| nps as f64).into()),
+        ("pv", pv.into()),
+    ]
+}
+
+fn records(tournament: &Tournament, from: usize, count: usize) -> Json {
+    let records = tournament
+        .records(from, count)
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            let moves = record.moves.iter().map(ToString::to_string).collect::<Vec<_>>();
+            let analyses = record.analyses.iter().map(|analysis| {
+                analysis
+                    .as_ref()
+                    .map(|analysis| Json::Object(analysis_fields(analysis)))
+            });
+            Json::object([
+                ("index", (from + index).into()),
+                ("red", record.red.into()),
+                ("black", record.black.into()),
+                ("start", record.start.to_rfc3339().into()),
+                ("duration", (record.duration.as_millis() as f64).into()),
+                ("fen", record.fen.into()),
+                ("red_turn", record.red_turn.into()),
+                ("moves", moves.into()),
+                ("times", record.times.into()),
+                ("analyses", analyses.collect::<Vec<_>>().into()),
+                ("verdict", record.conclusion.verdict.to_string().into()),
+                ("reason", record.conclusion.reason.to_string().into()),
+            ])
+        });
+    Json::Array(records.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbiter::tournament::record::Record;
+    use crate::arbiter::tournament::rules::Rules;
+    use crate::protocol::{Conclusion, Reason, Verdict};
+    use chinese_chess::game::Game;
+    use chrono::Local;
+
+    #[test]
+    fn errors() {
+        let tournament = Tournament::new(Rules::new());
+        let tournament = tournament.read().unwrap();
+
+        assert_eq!(answer(&tournament, "GET /nowhere HTTP/1.1").status, "404 Not Found");
+        assert_eq!(
+            answer(&tournament, "POST /api/players HTTP/1.1").status,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(answer(&tournament, "GET").status, "400 Bad Request");
+        assert_eq!(respond(&tournament, "/api/records?from=x").status, "400 Bad Request");
+        assert_eq!(
+            respond(&tournament, "/api/records?from=1&last=1").status,
+            "400 Bad Request"
+        );
+        assert_eq!(answer(&tournament, "GET /api/players HTTP/1.1").body, "[]");
+    }
+
+    #[test]
+    fn record_pages() {
+        let path = std::env::temp_dir().join(format!("http-records-{}.txt", std::process::id()));
+        for red in ["a", "b", "c"] {
+            let conclusion = Conclusion::new(Verdict::Draw, Reason::Agreement);
+            let record = Record::new(
+                red.into(),
+                "d".into(),
+                Local::now(),
+                Duration::ZERO,
+                &Game::opening(),
+                Vec::new(),
+                conclusion,
+            );
+            record.append(&path).unwrap();
+        }
+
+        let tournament = Tournament::new(Rules::new());
+        let mut tournament = tournament.write().unwrap();
+        tournament.open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let reds = |target| {
+            let response = respond(&tournament, target);
+            assert_eq!(response.status, "200 OK");
+            let reds = response.body.split(r#""red":""#).skip(1);
+            reds.map(|red| red[..1].to_owned()).collect::<Vec<_>>()
+        };
+        assert_eq!(reds("/api/records"), ["a", "b", "c"]);
+        assert_eq!(reds("/api/records?from=1"), ["b", "c"]);
+        assert_eq!(reds("/api/records?last=2"), ["b", "c"]);
+        assert_eq!(reds("/api/records?last=5"), ["a", "b", "c"]);
+        assert_eq!(reds("/api/records?from=3"), Vec::<String>::new());
+        assert!(respond(&tournament, "/api/records?last=1")
+            .body
+            .contains(r#""index":2"#));
+    }
+}
@@ -1,49 +1,201 @@
 use chrono::Local;
-use clap::error::ErrorKind;
 use clap::Parser;
 use env_logger::Target;
+use frontend::arbiter::config::Config;
 use frontend::arbiter::control;
+use frontend::arbiter::http;
+use frontend::arbiter::spectator;
+use frontend::arbiter::tournament::adjudication::ScoreRule;
+use frontend::arbiter::tournament::instance::Session;
+use frontend::arbiter::tournament::rules::{IllegalMovePolicy, Rules};
 use frontend::arbiter::tournament::Tournament;
 use frontend::line_stream::AsyncLineStream;
-use frontend::protocol::{PlayerMessage, Protocol};
+use frontend::protocol::{ArbiterMessage, Capabilities, Capability, PlayerMessage, Protocol, PROTOCOL_VERSION};
 use log::{info, warn, LevelFilter};
 use rustyline::ExternalPrinter;
 use smol::net::TcpStream as AsyncTcpStream;
 use std::io::Write;
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::Duration;
+
+// how long a new connection has for each message of the handshake before it is dropped
+const HANDSHAKE_TIME: Duration = Duration::from_secs(10);
 
 #[derive(Parser)]
 struct Arguments {
-    #[clap(short, long, default_value_t = 6000)]
-    port: u16,
+    #[clap(
+        short,
+        long,
+        help = "Address to accept players and spectators on, such as 0.0.0.0 to host other machines, 127.0.0.1 if unspecified"
+    )]
+    bind: Option<IpAddr>,
+
+    #[clap(short, long, help = "Port to accept players and spectators on, 6000 if unspecified")]
+    port: Option<u16>,
+
+    #[clap(
+        long,
+        help = "Port to serve the status API and dashboard on locally, disabled if unspecified"
+    )]
+    http: Option<u16>,
+
+    #[clap(
+        long,
+        help = "Key every connection must authenticate with, unless its player has a key of its own in the config"
+    )]
+    secret: Option<String>,
 
     #[clap(short, long, help = "Commands to execute on startup")]
     exec: Vec<String>,
 
+    #[clap(
+        short,
+        long,
+        help = "Config file with settings and events to run on startup, with the flags given here taking precedence"
+    )]
+    config: Option<PathBuf>,
+
+    #[clap(long, help = "Milliseconds each player gets for every move, 2000 if unspecified")]
+    move_time: Option<u32>,
+
+    #[clap(
+        long,
+        help = "Forfeit players that support stop once they take this many milliseconds past the move time, never if unspecified"
+    )]
+    grace_time: Option<u32>,
+
+    #[clap(
+        long,
+        help = "File with positions to start games from, as PGN games or one FEN per line"
+    )]
+    openings: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "What to do with illegal moves: forfeit, retry:<n> or warn:<n> to allow n illegal moves per game, warn:3 if unspecified"
+    )]
+    illegal_move: Option<IllegalMovePolicy>,
+
+    #[clap(
+        long,
+        help = "Adjudicate a draw once both players report |score| <= threshold for this many moves, as <moves>:<threshold>"
+    )]
+    draw_adjudication: Option<ScoreRule>,
+
+    #[clap(
+        long,
+        help = "Adjudicate a win once one player reports score <= -threshold and the other >= threshold for this many moves, as <moves>:<threshold>"
+    )]
+    resign_adjudication: Option<ScoreRule>,
+
+    #[clap(long, help = "Adjudicate a draw once a game reaches this many plies")]
+    max_plies: Option<u32>,
+
+    #[clap(long, help = "Most games running at once, unlimited if unspecified")]
+    max_games: Option<u32>,
+
+    #[clap(
+        long,
+        help = "Most games running at once for any single player, unlimited if unspecified"
+    )]
+    max_player_games: Option<u32>,
+
+    #[clap(long, help = "File to load previous game records from and append finished games to")]
+    games: Option<PathBuf>,
+
+    #[clap(long, help = "File to append finished games to in PGN")]
+    pgn: Option<PathBuf>,
+
     #[clap(long, default_value_t = LevelFilter::Trace)]
     log: LevelFilter,
 
     #[clap(long)]
-    log_file: Option<String>,
+    log_file: Option<PathBuf>,
+}
+
+impl Arguments {
+    // the settings given on the command line, which take precedence over the ones in the config file
+    fn overrides(&self) -> Config {
+        Config {
+            bind: self.bind,
+            port: self.port,
+            http: self.http,
+            log_file: self.log_file.clone(),
+            games: self.games.clone(),
+            pgn: self.pgn.clone(),
+            openings: self.openings.clone(),
+            move_time: self.move_time,
+            grace_time: self.grace_time,
+            illegal_move: self.illegal_move,
+            draw_adjudication: self.draw_adjudication,
+            resign_adjudication: self.resign_adjudication,
+            max_plies: self.max_plies,
+            max_games: self.max_games,
+            max_player_games: self.max_player_games,
+            secret: self.secret.clone(),
+            ..Config::default()
+        }
+    }
 }
 
 fn main() {
     let arguments = Arguments::parse();
-    let console = setup_console(&arguments);
-    let tournament: Arc<RwLock<Tournament>> = Tournament::new();
+    let overrides = arguments.overrides();
+    let config = match &arguments.config {
+        Some(path) => Config::load(path).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    let config = config.overridden(&overrides);
+
+    let console = setup_console(arguments.log, config.log_file.as_deref());
+    let tournament: Arc<RwLock<Tournament>> = Tournament::new(config.rules(Rules::new()));
+
+    {
+        let mut tournament = tournament.write().unwrap();
+
+        if let Some(path) = &config.games {
+            tournament.open(path).expect("Failed to open game records");
+        }
+
+        config.apply(&mut tournament).expect("Failed to apply config");
+    }
 
     {
         let tournament = tournament.clone();
-        thread::spawn(move || control::begin(tournament, &arguments.exec, console));
+        let exec = config.events.iter().chain(&arguments.exec).cloned().collect::<Vec<_>>();
+        let config = arguments.config.clone().map(|path| (path, overrides));
+        thread::spawn(move || control::begin(tournament, &exec, config, console));
     }
 
     smol::block_on(async {
-        let address = format!("127.0.0.1:{}", arguments.port);
-        let listener = smol::net::TcpListener::bind(&address).await.unwrap();
+        smol::spawn(Tournament::monitor(tournament.clone())).detach();
+
+        if let Some(port) = config.http {
+            let tournament = tournament.clone();
+            let address = SocketAddr::from(([127, 0, 0, 1], port));
+            smol::spawn(async move {
+                if let Err(error) = http::serve(tournament, address).await {
+                    warn!("status server stopped with error {error}");
+                }
+            })
+            .detach();
+        }
+
+        let bind = config.bind.unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let address = SocketAddr::new(bind, config.port.unwrap_or(6000));
+        let listener = smol::net::TcpListener::bind(address).await.unwrap();
         info!("server listening at {address}");
 
+        if !address.ip().is_loopback() && tournament.read().unwrap().access().is_open() {
+            warn!("accepting connections from other machines without requiring any key");
+        }
+
         loop {
             let (stream, address) = listener.accept().await.unwrap();
             info!("received incoming connection from {address}");
@@ -63,26 +215,114 @@ async fn initialize_connection(
     tournament: Arc<RwLock<Tournament>>,
     stream: AsyncLineStream,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let read = async || Protocol::decode_player(&stream.read_line().await?);
+    // a message that cannot be decoded ends the handshake, with an error sent back once that capability is known
+    let read = async |capabilities: Capabilities| -> Result<PlayerMessage, Box<dyn std::error::Error>> {
+        let line = stream.read_line_within(HANDSHAKE_TIME).await?;
+        match Protocol::decode_handshake(&line) {
+            Ok(message) => Ok(message),
+            Err(error) => {
+                let description = format!("failed to decode handshake message '{line}': {error}");
+                if capabilities.contains(Capability::Error) {
+                    let message = ArbiterMessage::Error { error };
+                    stream.write_line(Protocol::encode_arbiter(&message)).await?;
+                }
+                Err(description.into())
+            }
+        }
+    };
+
+    let PlayerMessage::Init { version, capabilities } = read(Capabilities::none()).await? else {
+        return Err("expected init message".into());
+    };
+
+    // version 1 clients do not expect an accept message and never receive any version 2 messages
+    let (version, capabilities) = match version {
+        0 => return Err(format!("unsupported protocol version {version}").into()),
+        1 => (1, Capabilities::none()),
+        _ => {
+            let version = version.min(PROTOCOL_VERSION);
+            let capabilities = capabilities.intersect(Capabilities::all());
+            let accept = ArbiterMessage::Accept { version, capabilities };
+            stream.write_line(Protocol::encode_arbiter(&accept)).await?;
+            (version, capabilities)
+        }
+    };
+
+    // the key comes first if the connection authenticates at all, which version 1 clients cannot do
+    let mut message = read(capabilities).await?;
+    let mut key = None;
+    if version >= 2
+        && let PlayerMessage::Auth { key: given } = message
+    {
+        key = Some(given);
+        message = read(capabilities).await?;
+    }
 
-    let Some(PlayerMessage::Init { version: 1 }) = read().await else {
-        return Err("expected init message with version 1".into());
+    let authorize = async |name: Option<&str>| -> Result<(), Box<dyn std::error::Error>> {
+        let access = tournament
+            .read()
+            .map_err(|_| "tournament poisoned")?
+            .access()
+            .check(name, key.as_deref());
+        if let Err(reason) = access {
+            if version >= 2 {
+                let message = ArbiterMessage::Rejected { reason: reason.clone() };
+                stream.write_line(Protocol::encode_arbiter(&message)).await?;
+            }
+            return Err(format!("rejected connection because {reason}").into());
+        }
+        Ok(())
     };
 
-    let Some(PlayerMessage::Info { name }) = read().await else {
-        return Err("expected info message".into());
+    let name = match message {
+        PlayerMessage::Info { name } => {
+            authorize(Some(&name)).await?;
+            name
+        }
+        PlayerMessage::Resume { name, token } if capabilities.contains(Capability::Resume) => {
+            authorize(Some(&name)).await?;
+            let session = tournament
+                .read()
+                .map_err(|_| "tournament poisoned")?
+                .session(&name, &token);
+            if let Some(session) = session {
+                info!("connection resumed session of player '{name}' with protocol version {version}");
+                let message = ArbiterMessage::Session { token };
+                stream.write_line(Protocol::encode_arbiter(&message)).await?;
+                session.resume(stream);
+                return Ok(());
+            }
+
+            info!("connection has no session to resume for player '{name}', joining as a new instance");
+            name
+        }
+        PlayerMessage::Spectate if version >= 2 => {
+            authorize(None).await?;
+            info!("connection initialized as spectator with protocol version {version}");
+            return Ok(spectator::spectate(tournament, stream, capabilities).await?);
+        }
+        _ => return Err("expected info message".into()),
     };
 
-    info!("connection initialized as instance for player '{name}'");
+    // the token goes out before joining so that it arrives ahead of the first game
+    let session = Session::new(stream.clone());
+    if capabilities.contains(Capability::Resume) {
+        let message = ArbiterMessage::Session {
+            token: session.token.clone(),
+        };
+        stream.write_line(Protocol::encode_arbiter(&message)).await?;
+    }
+
+    info!("connection initialized as instance for player '{name}' with protocol version {version}");
     let mut tournament = tournament.write().map_err(|_| "tournament poisoned")?;
-    tournament.join(&name, stream);
+    tournament.join(&name, session, capabilities);
     Ok(())
 }
 
-fn setup_console(arguments: &Arguments) -> rustyline::DefaultEditor {
+fn setup_console(log: LevelFilter, log_file: Option<&Path>) -> rustyline::DefaultEditor {
     let mut console = rustyline::DefaultEditor::new().unwrap();
 
-    let target = if let Some(path) = &arguments.log_file {
+    let target = if let Some(path) = log_file {
         let file = std::fs::OpenOptions::new()
             .append(true)
             .create(true)
@@ -99,7 +339,7 @@ fn setup_console(arguments: &Arguments) -> rustyline::DefaultEditor {
                 let line = String::from_utf8_lossy(buf).into_owned();
                 self.printer
                     .print(line)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
 
                 Ok(buf.len())
             }
@@ -114,7 +354,7 @@ fn setup_console(arguments: &Arguments) -> rustyline::DefaultEditor {
     };
 
     env_logger::Builder::from_default_env()
-        .filter_level(arguments.log)
+        .filter_level(log)
         .filter_module("rustyline", LevelFilter::Warn)
         .format(|buf, record| {
             writeln!(
@@ -1,2 +1,6 @@
+pub mod config;
 pub mod control;
+pub mod http;
+pub mod json;
+pub mod spectator;
 pub mod tournament;
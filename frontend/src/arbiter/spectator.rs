@@ -0,0 +1,102 @@
+use crate::arbiter::tournament::Tournament;
+use crate::line_stream::AsyncLineStream;
+use crate::protocol::{ArbiterMessage, Capabilities, Capability, PlayerMessage, Protocol};
+use log::{debug, info, warn};
+use smol::channel::Receiver;
+use std::sync::{Arc, RwLock};
+
+enum Event {
+    Request(Option<String>),   // a line from the spectator, or none once it disconnected
+    Broadcast(Option<String>), // a line of the watched game, or none once the game ended
+}
+
+// serves a connection that asked to spectate, answering list and watch requests and forwarding every message of the
+// watched game until it ends or another game is watched
+pub async fn spectate(
+    tournament: Arc<RwLock<Tournament>>,
+    stream: AsyncLineStream,
+    capabilities: Capabilities,
+) -> Result<(), String> {
+    let stream = Arc::new(stream);
+
+    // lines are read on a separate task as reading is interrupted whenever the watched game sends something
+    let (sender, requests) = smol::channel::unbounded();
+    let reader = {
+        let stream = stream.clone();
+        smol::spawn(async move {
+            while let Ok(line) = stream.read_line().await {
+                if sender.send(line).await.is_err() {
+                    break;
+                }
+            }
+        })
+    };
+
+    let mut watching: Option<Receiver<String>> = None;
+    let write = async |message: &ArbiterMessage| {
+        stream
+            .write_line(Protocol::encode_arbiter(message))
+            .await
+            .map_err(|error| error.to_string())
+    };
+
+    loop {
+        let request = async { Event::Request(requests.recv().await.ok()) };
+        let event = match &watching {
+            Some(game) => smol::future::or(request, async { Event::Broadcast(game.recv().await.ok()) }).await,
+            None => request.await,
+        };
+
+        let line = match event {
+            Event::Request(Some(line)) => line,
+            Event::Request(None) => break,
+            Event::Broadcast(Some(line)) => {
+                stream.write_line(line).await.map_err(|error| error.to_string())?;
+                continue;
+            }
+            Event::Broadcast(None) => {
+                watching = None;
+                continue;
+            }
+        };
+
+        match Protocol::decode_player(&line) {
+            Ok(PlayerMessage::List) => {
+                let games = tournament.read().unwrap().iter_games().collect::<Vec<_>>();
+                for game in games {
+                    let message = ArbiterMessage::Running {
+                        id: game.id,
+                        red: game.red.1.clone(),
+                        black: game.black.1.clone(),
+                    };
+                    write(&message).await?;
+                }
+                write(&ArbiterMessage::Listed).await?;
+            }
+            Ok(PlayerMessage::Watch { id }) => {
+                let game = tournament.read().unwrap().game(id);
+                match game {
+                    Some(game) => {
+                        debug!("spectator watching game #{id}");
+                        watching = Some(game.watch());
+                    }
+                    None => {
+                        watching = None;
+                        write(&ArbiterMessage::Missing { id }).await?;
+                    }
+                }
+            }
+            Ok(_) => return Err(format!("unexpected message from spectator '{line}'")),
+            Err(error) => {
+                warn!("failed to decode spectator message '{line}': {error}");
+                if capabilities.contains(Capability::Error) {
+                    write(&ArbiterMessage::Error { error }).await?;
+                }
+            }
+        }
+    }
+
+    info!("spectator disconnected");
+    reader.cancel().await;
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+// who may connect, which is anyone unless there is a shared secret or a key for the name of the player
+#[derive(Clone, Default, Debug)]
+pub struct Access {
+    pub secret: Option<String>,        // required from every connection without a key of its own
+    pub keys: HashMap<String, String>, // required from instances of each player instead of the secret
+}
+
+impl Access {
+    // checks the key a connection authenticated with, as an instance of the named player or as a spectator if none
+    pub fn check(&self, name: Option<&str>, key: Option<&str>) -> Result<(), String> {
+        let (expected, whom) = match name.and_then(|name| self.keys.get(name).map(|key| (key, name))) {
+            Some((expected, name)) => (expected, format!("player '{name}'")),
+            None => match &self.secret {
+                Some(secret) => (secret, "this arbiter".to_owned()),
+                None => return Ok(()),
+            },
+        };
+
+        match key {
+            Some(key) if matches(expected, key) => Ok(()),
+            Some(_) => Err(format!("wrong key for {whom}")),
+            None => Err(format!("{whom} requires a key")),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.secret.is_none() && self.keys.is_empty()
+    }
+}
+
+// compares every byte regardless of where the first difference is, so the time taken does not give the key away
+fn matches(expected: &str, key: &str) -> bool {
+    let difference = expected
+        .bytes()
+        .zip(key.bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    difference == 0 && expected.len() == key.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut access = Access::default();
+        assert!(access.check(Some("robot"), None).is_ok());
+
+        access.keys.insert("robot".to_owned(), "beep".to_owned());
+        assert!(access.check(Some("robot"), Some("beep")).is_ok());
+        assert!(access.check(Some("robot"), Some("beeps")).is_err());
+        assert!(access.check(Some("robot"), None).is_err());
+        assert!(access.check(Some("other"), None).is_ok());
+        assert!(access.check(None, None).is_ok());
+
+        // the secret does not stand in for the key of a player
+        access.secret = Some("open sesame".to_owned());
+        assert!(access.check(Some("robot"), Some("open sesame")).is_err());
+        assert!(access.check(Some("other"), Some("open sesame")).is_ok());
+        assert!(access.check(Some("other"), Some("beep")).is_err());
+        assert!(access.check(None, None).is_err());
+    }
+}
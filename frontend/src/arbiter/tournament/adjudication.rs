@@ -0,0 +1,178 @@
+use crate::protocol::{Conclusion, Reason, Verdict};
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Adjudication {
+    pub draw: Option<ScoreRule>,   // both players report a score close to zero
+    pub resign: Option<ScoreRule>, // one player reports losing and the other agrees it is winning
+    pub max_plies: Option<u32>,    // hard cap on the length of a game, which is then drawn
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ScoreRule {
+    pub moves: u32, // consecutive moves of each player the rule has to hold for
+    pub threshold: i32,
+}
+
+// tracks the scores reported by both players over the course of one game
+pub struct Adjudicator {
+    adjudication: Adjudication,
+    scores: [Option<i32>; 2], // most recent score of red and black from their own perspective
+    draw_plies: u32,
+    resign_moves: [u32; 2],
+}
+
+impl Adjudicator {
+    pub fn new(adjudication: Adjudication) -> Self {
+        Self {
+            adjudication,
+            scores: [None, None],
+            draw_plies: 0,
+            resign_moves: [0, 0],
+        }
+    }
+
+    // record the last score reported by a player before it made its move, if any
+    pub fn report(&mut self, red: bool, score: Option<i32>) {
+        let index = if red { 0 } else { 1 };
+        self.scores[index] = score;
+
+        if let Some(rule) = self.adjudication.draw {
+            let quiet = score.is_some_and(|score| score.abs() <= rule.threshold);
+            self.draw_plies = if quiet { self.draw_plies + 1 } else { 0 };
+        }
+
+        if let Some(rule) = self.adjudication.resign {
+            let opponent = self.scores[1 - index];
+            let losing = score.is_some_and(|score| score <= -rule.threshold)
+                && opponent.is_some_and(|score| score >= rule.threshold);
+            self.resign_moves[index] = if losing { self.resign_moves[index] + 1 } else { 0 };
+        }
+    }
+
+    pub fn adjudicate(&self, plies: usize) -> Option<Conclusion> {
+        if let Some(rule) = self.adjudication.resign {
+            for (red, moves) in [(true, self.resign_moves[0]), (false, self.resign_moves[1])] {
+                if moves >= rule.moves.max(1) {
+                    return Some(Conclusion::forfeit(red, Reason::WinAdjudication));
+                }
+            }
+        }
+
+        if let Some(rule) = self.adjudication.draw
+            && self.draw_plies >= rule.moves.max(1) * 2
+        {
+            return Some(Conclusion::new(Verdict::Draw, Reason::DrawAdjudication));
+        }
+
+        if let Some(max_plies) = self.adjudication.max_plies
+            && plies >= max_plies as usize
+        {
+            return Some(Conclusion::new(Verdict::Draw, Reason::PlyLimit));
+        }
+
+        None
+    }
+}
+
+impl Display for ScoreRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.moves, self.threshold)
+    }
+}
+
+impl FromStr for ScoreRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid score rule '{s}', expected <moves>:<threshold>");
+        let (moves, threshold) = s.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Self {
+            moves: moves.parse().map_err(|_| invalid())?,
+            threshold: threshold.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adjudicator(draw: Option<ScoreRule>, resign: Option<ScoreRule>, max_plies: Option<u32>) -> Adjudicator {
+        Adjudicator::new(Adjudication {
+            draw,
+            resign,
+            max_plies,
+        })
+    }
+
+    #[test]
+    fn draw_needs_both_players_quiet() {
+        let rule = ScoreRule {
+            moves: 2,
+            threshold: 50,
+        };
+        let mut adjudicator = adjudicator(Some(rule), None, None);
+
+        adjudicator.report(true, Some(10));
+        adjudicator.report(false, Some(-20));
+        adjudicator.report(true, Some(0));
+        assert_eq!(adjudicator.adjudicate(3), None);
+
+        adjudicator.report(false, Some(60));
+        assert_eq!(adjudicator.adjudicate(4), None);
+
+        for (red, score) in [(true, 5), (false, -5), (true, 50)] {
+            adjudicator.report(red, Some(score));
+        }
+        assert_eq!(adjudicator.adjudicate(7), None);
+
+        adjudicator.report(false, Some(-50));
+        let conclusion = Conclusion::new(Verdict::Draw, Reason::DrawAdjudication);
+        assert_eq!(adjudicator.adjudicate(8), Some(conclusion));
+    }
+
+    #[test]
+    fn missing_score_resets_streak() {
+        let rule = ScoreRule {
+            moves: 1,
+            threshold: 50,
+        };
+        let mut adjudicator = adjudicator(Some(rule), None, None);
+
+        adjudicator.report(true, Some(0));
+        adjudicator.report(false, None);
+        adjudicator.report(true, Some(0));
+        assert_eq!(adjudicator.adjudicate(3), None);
+    }
+
+    #[test]
+    fn resign_needs_agreement() {
+        let rule = ScoreRule {
+            moves: 2,
+            threshold: 1000,
+        };
+        let mut adjudicator = adjudicator(None, Some(rule), None);
+
+        adjudicator.report(true, Some(-1500));
+        adjudicator.report(false, Some(1500));
+        adjudicator.report(true, Some(-1500));
+        assert_eq!(adjudicator.adjudicate(3), None);
+
+        adjudicator.report(false, Some(2000));
+        adjudicator.report(true, Some(-2000));
+        let conclusion = Conclusion::new(Verdict::BlackWon, Reason::WinAdjudication);
+        assert_eq!(adjudicator.adjudicate(5), Some(conclusion));
+    }
+
+    #[test]
+    fn ply_limit() {
+        let adjudicator = adjudicator(None, None, Some(10));
+        assert_eq!(adjudicator.adjudicate(9), None);
+
+        let conclusion = Conclusion::new(Verdict::Draw, Reason::PlyLimit);
+        assert_eq!(adjudicator.adjudicate(10), Some(conclusion));
+    }
+}
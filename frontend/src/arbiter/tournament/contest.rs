@@ -0,0 +1,543 @@
+use crate::arbiter::tournament::adjudication::Adjudicator;
+use crate::arbiter::tournament::instance::Instance;
+use crate::arbiter::tournament::live::Live;
+use crate::arbiter::tournament::rules::Rules;
+use crate::arbiter::tournament::PlayerId;
+use crate::protocol::{Analysis, ArbiterMessage, Capability, Conclusion, PlayerMessage, Reason, Verdict};
+use chinese_chess::display_format::DisplayFormat;
+use chinese_chess::game::Game;
+use chinese_chess::notation;
+use log::{debug, info, trace, warn};
+use std::time::{Duration, Instant};
+
+// an ongoing game between two instances, with home playing red
+pub struct Contest<'a> {
+    game: Game,
+    home: &'a Instance,
+    away: &'a Instance,
+    live: &'a Live,
+    rules: Rules,
+    draw_offer: Option<PlayerId>,
+    illegal_moves: [u32; 2], // for red and black
+    adjudicator: Adjudicator,
+    times: Vec<u32>,                 // milliseconds spent on each move
+    analyses: Vec<Option<Analysis>>, // the last analysis sent during each move
+    turn_start: Option<Instant>,     // kept when a turn is interrupted so that resuming does not reset its time
+}
+
+// why a turn could not be completed by the instance with the id
+enum Fault {
+    Lost(PlayerId),      // the connection failed, which the instance may recover from by resuming its session
+    Violation(PlayerId), // the instance broke the protocol and forfeits right away
+}
+
+impl From<PlayerId> for Fault {
+    fn from(id: PlayerId) -> Self {
+        Fault::Lost(id)
+    }
+}
+
+impl<'a> Contest<'a> {
+    const RESUME_TIME: Duration = Duration::from_secs(10);
+
+    pub fn new(game: Game, home: &'a Instance, away: &'a Instance, live: &'a Live, rules: Rules) -> Self {
+        Self {
+            game,
+            home,
+            away,
+            live,
+            rules,
+            draw_offer: None,
+            illegal_moves: [0, 0],
+            adjudicator: Adjudicator::new(rules.adjudication),
+            times: Vec::new(),
+            analyses: Vec::new(),
+            turn_start: None,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn times(&self) -> &[u32] {
+        &self.times
+    }
+
+    pub fn analyses(&self) -> &[Option<Analysis>] {
+        &self.analyses
+    }
+
+    pub async fn run(&mut self) -> Result<Conclusion, PlayerId> {
+        loop {
+            if let Some(outcome) = self.game.outcome() {
+                debug!(
+                    "game between '{}' and '{}' concluded normally with {outcome}",
+                    self.home.name, self.away.name
+                );
+                return Ok(outcome.into());
+            }
+
+            if let Some(conclusion) = self.adjudicator.adjudicate(self.game.history().len()) {
+                debug!(
+                    "game between '{}' and '{}' adjudicated with {conclusion}",
+                    self.home.name, self.away.name
+                );
+                return Ok(conclusion);
+            }
+
+            let turn = match self.turn().await {
+                Ok(turn) => turn,
+                Err(Fault::Lost(id)) => {
+                    self.resume(id).await?;
+                    continue;
+                }
+                Err(Fault::Violation(id)) => return Err(id),
+            };
+
+            if let Some(conclusion) = turn {
+                debug!(
+                    "game between '{}' and '{}' concluded with {conclusion}",
+                    self.home.name, self.away.name
+                );
+                return Ok(conclusion);
+            }
+        }
+    }
+
+    async fn turn(&mut self) -> Result<Option<Conclusion>, Fault> {
+        let red = self.game.red_turn();
+        let (instance, opponent) = if red {
+            (self.home, self.away)
+        } else {
+            (self.away, self.home)
+        };
+        let mut offering = false;
+        let mut analysis = None;
+        let resumed = self.turn_start.is_some();
+        let started = *self.turn_start.get_or_insert_with(Instant::now);
+        if !resumed {
+            self.live.begin_turn(red);
+        }
+
+        // a resumed turn only has the time that was left when the connection was lost
+        let mut time = self
+            .rules
+            .move_time
+            .saturating_sub(started.elapsed().as_millis() as u32);
+        let mv = 'prompt: loop {
+            trace!("prompting '{}' for next move with {time}ms remaining", instance.name);
+            instance.send(&ArbiterMessage::Prompt { time }).await?;
+
+            // once out of time, ask for a move right away and forfeit only if the rules allow a grace period and the
+            // instance is able to stop, otherwise keep waiting for it
+            let mut deadline = Some(Instant::now() + Duration::from_millis(time as u64));
+            let mut stopped = false;
+
+            loop {
+                let received = match deadline {
+                    Some(deadline) => instance.recv_before(deadline).await?,
+                    None => Some(instance.recv().await?),
+                };
+                let Some(message) = received else {
+                    if stopped {
+                        warn!("'{}' failed to play a move in time", instance.name);
+                        return Ok(Some(Conclusion::forfeit(red, Reason::Timeout)));
+                    }
+
+                    instance.notify(&ArbiterMessage::Stop, Capability::Stop).await?;
+                    deadline = match self.rules.grace_time {
+                        Some(grace) if instance.supports(Capability::Stop) => {
+                            deadline.map(|deadline| deadline + Duration::from_millis(grace as u64))
+                        }
+                        _ => None,
+                    };
+                    stopped = true;
+                    continue;
+                };
+
+                match message {
+                    PlayerMessage::Play { mv } => {
+                        let validated = notation::validate(&self.game, mv);
+                        trace!(
+                            "'{}' requested to play {} move {mv}",
+                            instance.name,
+                            if validated.is_ok() { "legal" } else { "illegal" }
+                        );
+
+                        if let Err(error) = validated {
+                            let illegal_moves = &mut self.illegal_moves[if red { 0 } else { 1 }];
+                            *illegal_moves += 1;
+                            warn!(
+                                "'{}' played illegal move {mv} ({} this game) in position {}: {error}",
+                                instance.name,
+                                illegal_moves,
+                                self.game.display(DisplayFormat::string())
+                            );
+
+                            if !self.rules.illegal_move.allows(*illegal_moves) {
+                                return Ok(Some(Conclusion::forfeit(red, Reason::IllegalMove)));
+                            }
+
+                            if self.rules.illegal_move.warns() {
+                                instance
+                                    .notify(&ArbiterMessage::Illegal { mv }, Capability::Illegal)
+                                    .await?;
+                            }
+
+                            time = self.rules.move_time;
+                            continue 'prompt;
+                        }
+
+                        self.game.make_move(mv);
+                        break 'prompt mv;
+                    }
+                    PlayerMessage::Analysis { analysis: latest } if instance.supports(Capability::Info) => {
+                        trace!("'{}' analysis {latest}", instance.name);
+                        self.live.analyze(red, &latest);
+                        analysis = Some(latest);
+                    }
+                    PlayerMessage::Pong { .. } if instance.supports(Capability::Ping) => {}
+                    PlayerMessage::Resign if instance.supports(Capability::Resign) => {
+                        return Ok(Some(Conclusion::forfeit(red, Reason::Resignation)));
+                    }
+                    PlayerMessage::Draw { accept: false } if instance.supports(Capability::Draw) => offering = true,
+                    PlayerMessage::Draw { accept: true } if instance.supports(Capability::Draw) => {
+                        if self.draw_offer == Some(opponent.id) {
+                            return Ok(Some(Conclusion::new(Verdict::Draw, Reason::Agreement)));
+                        }
+                        warn!("'{}' accepted a draw that was never offered", instance.name);
+                    }
+                    _ => {
+                        warn!(
+                            "disconnecting '{}' due to unexpected message during game",
+                            instance.name
+                        );
+                        instance.close();
+                        return Err(Fault::Violation(instance.id));
+                    }
+                }
+            }
+        };
+
+        self.turn_start = None;
+        self.times.push(started.elapsed().as_millis() as u32);
+        self.live.end_turn(mv);
+        let score = analysis.as_ref().map(|analysis| analysis.score);
+        self.adjudicator.report(red, score);
+        self.analyses.push(analysis);
+
+        // both sends are always completed so that the other side still gets the move if one side disconnected
+        let message = ArbiterMessage::Update { mv };
+        let (sent, opponent_sent) = smol::future::zip(instance.send(&message), opponent.send(&message)).await;
+        sent.and(opponent_sent)?;
+
+        // a pending offer expires once the opponent plays a move instead of accepting
+        self.draw_offer = None;
+        if offering {
+            debug!("'{}' offered a draw", instance.name);
+            self.draw_offer = Some(instance.id);
+            opponent.notify(&ArbiterMessage::Draw, Capability::Draw).await?;
+        }
+
+        Ok(None)
+    }
+
+    // gives an instance that lost its connection a chance to take its session over with a new one, after which it is
+    // sent the game so far and the interrupted turn continues with the time it had left
+    async fn resume(&mut self, id: PlayerId) -> Result<(), PlayerId> {
+        let instance = if id == self.home.id { self.home } else { self.away };
+        warn!("'{}' lost its connection during game", instance.name);
+        if !instance.resumed(Self::RESUME_TIME).await {
+            return Err(id);
+        }
+
+        info!("'{}' resumed its session during game", instance.name);
+        let moves = self.game.history().iter().map(|&(mv, _)| mv).collect::<Vec<_>>();
+        let mut start = self.game.clone();
+        moves.iter().for_each(|_| {
+            start.undo_move();
+        });
+
+        instance.send(&ArbiterMessage::from_game(&start)).await?;
+        let deadline = Instant::now() + Self::RESUME_TIME;
+        loop {
+            match instance.recv_before(deadline).await? {
+                Some(PlayerMessage::Ready) => break,
+                Some(_) => continue,
+                None => return Err(id),
+            }
+        }
+
+        for mv in moves {
+            instance.send(&ArbiterMessage::Update { mv }).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arbiter::tournament::instance::Session;
+    use crate::arbiter::tournament::rules::IllegalMovePolicy;
+    use crate::conformance::mock::MockPlayer;
+    use crate::line_stream::AsyncLineStream;
+    use crate::protocol::{Capabilities, Protocol};
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    // a connection with a mock player on the other end of it
+    fn connect() -> (AsyncLineStream, MockPlayer) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let player = MockPlayer::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let stream = smol::net::TcpStream::try_from(stream).unwrap();
+        (AsyncLineStream::new(stream), player)
+    }
+
+    fn instance(id: PlayerId, capabilities: &[Capability]) -> (Instance, Arc<Session>, MockPlayer) {
+        let (stream, player) = connect();
+        let session = Session::new(stream);
+        let capabilities = Capabilities::from_iter(capabilities.iter().copied());
+        let instance = Instance::new(id, format!("player{id}"), 0, session.clone(), capabilities);
+        (instance, session, player)
+    }
+
+    fn script(player: MockPlayer, script: impl FnOnce(&MockPlayer) -> Result<(), String> + Send + 'static) -> Script {
+        std::thread::spawn(move || script(&player).map(|_| player))
+    }
+
+    type Script = JoinHandle<Result<MockPlayer, String>>;
+
+    fn play(home: &Instance, away: &Instance, rules: Rules, scripts: [Script; 2]) -> (Conclusion, Vec<u32>, Game) {
+        let game = Game::opening();
+        let live = Live::new((home.id, home.name.clone()), (away.id, away.name.clone()), &game);
+        let mut contest = Contest::new(game, home, away, &live, rules);
+        let conclusion = smol::block_on(contest.run()).unwrap();
+
+        for script in scripts {
+            script.join().unwrap().unwrap();
+        }
+        (conclusion, contest.times().to_vec(), contest.game().clone())
+    }
+
+    fn expect(player: &MockPlayer, expected: &str) -> Result<(), String> {
+        let message = Protocol::encode_arbiter(&player.recv()?);
+        match message.starts_with(expected) {
+            true => Ok(()),
+            false => Err(format!("expected {expected} but received {message}")),
+        }
+    }
+
+    fn send(player: &MockPlayer, line: &str) -> Result<(), String> {
+        player.send_line(line)
+    }
+
+    fn rules(move_time: u32) -> Rules {
+        Rules {
+            move_time,
+            ..Rules::new()
+        }
+    }
+
+    #[test]
+    fn timeout() {
+        let rules = Rules {
+            grace_time: Some(100),
+            ..rules(100)
+        };
+
+        // a player that negotiated stop but does not play forfeits once the grace period passes
+        let (home, _, red) = instance(0, &[Capability::Stop]);
+        let (away, _, black) = instance(1, &[]);
+        let red = script(red, |red| {
+            expect(red, "prompt 100")?;
+            expect(red, "stop")
+        });
+        let black = script(black, |_| Ok(()));
+
+        let (conclusion, _, _) = play(&home, &away, rules, [red, black]);
+        assert_eq!(conclusion, Conclusion::forfeit(true, Reason::Timeout));
+
+        // while one that cannot be stopped is waited for
+        let (home, _, red) = instance(0, &[]);
+        let (away, _, black) = instance(1, &[Capability::Resign]);
+        let red = script(red, |red| {
+            expect(red, "prompt")?;
+            std::thread::sleep(Duration::from_millis(400));
+            send(red, "play h2e2")?;
+            expect(red, "update h2e2")
+        });
+        let black = script(black, |black| {
+            expect(black, "update h2e2")?;
+            expect(black, "prompt")?;
+            send(black, "resign")
+        });
+
+        let (conclusion, times, _) = play(&home, &away, rules, [red, black]);
+        assert_eq!(conclusion, Conclusion::forfeit(false, Reason::Resignation));
+        assert!(times[0] >= 400);
+    }
+
+    #[test]
+    fn illegal_move() {
+        let rules = Rules {
+            illegal_move: IllegalMovePolicy::Warn { limit: 1 },
+            ..rules(5000)
+        };
+
+        let (home, _, red) = instance(0, &[Capability::Illegal]);
+        let (away, _, black) = instance(1, &[]);
+        let red = script(red, |red| {
+            expect(red, "prompt")?;
+            send(red, "play h2e3")?;
+            expect(red, "illegal h2e3")?;
+            expect(red, "prompt")?;
+            send(red, "play h2e3")
+        });
+        let black = script(black, |_| Ok(()));
+
+        let (conclusion, _, game) = play(&home, &away, rules, [red, black]);
+        assert_eq!(conclusion, Conclusion::forfeit(true, Reason::IllegalMove));
+        assert!(game.history().is_empty());
+    }
+
+    #[test]
+    fn draw_agreement() {
+        let (home, _, red) = instance(0, &[Capability::Draw]);
+        let (away, _, black) = instance(1, &[Capability::Draw]);
+        let red = script(red, |red| {
+            expect(red, "prompt")?;
+            send(red, "draw offer")?;
+            send(red, "play h2e2")?;
+            expect(red, "update h2e2")
+        });
+        let black = script(black, |black| {
+            expect(black, "update h2e2")?;
+            expect(black, "draw")?;
+            expect(black, "prompt")?;
+            send(black, "draw accept")
+        });
+
+        let (conclusion, _, game) = play(&home, &away, rules(5000), [red, black]);
+        assert_eq!(conclusion, Conclusion::new(Verdict::Draw, Reason::Agreement));
+        assert_eq!(game.history().len(), 1);
+    }
+
+    #[test]
+    fn resume() {
+        // the red player loses its connection while prompted and takes its session over with a new one
+        let (home, session, red) = instance(0, &[Capability::Resume]);
+        let (away, _, black) = instance(1, &[Capability::Resign]);
+        let red = script(red, move |red| {
+            expect(red, "prompt")?;
+            send(red, "play h2e2")?;
+            expect(red, "update h2e2")?;
+            expect(red, "update h9g7")?;
+            expect(red, "prompt 5000")?;
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(())
+        });
+        let resumed = std::thread::spawn(move || {
+            let red = red.join().unwrap()?;
+            drop(red);
+
+            let (stream, red) = connect();
+            session.resume(stream);
+
+            // the game so far is replayed before the interrupted turn continues with the time it had left
+            let fen = Game::opening().board().fen();
+            expect(&red, &format!("game {fen} true"))?;
+            send(&red, "ready")?;
+            expect(&red, "update h2e2")?;
+            expect(&red, "update h9g7")?;
+            match red.recv()? {
+                ArbiterMessage::Prompt { time } if time <= 4500 => {}
+                message => {
+                    let message = Protocol::encode_arbiter(&message);
+                    return Err(format!("expected a shorter prompt but received {message}"));
+                }
+            }
+            send(&red, "play h0g2")?;
+            expect(&red, "update h0g2")?;
+            Ok(red)
+        });
+        let black = script(black, |black| {
+            expect(black, "update h2e2")?;
+            expect(black, "prompt")?;
+            send(black, "play h9g7")?;
+            expect(black, "update h9g7")?;
+            expect(black, "update h0g2")?;
+            expect(black, "prompt")?;
+            send(black, "resign")
+        });
+
+        let (conclusion, _, game) = play(&home, &away, rules(5000), [resumed, black]);
+        assert_eq!(conclusion, Conclusion::forfeit(false, Reason::Resignation));
+        assert_eq!(game.history().len(), 3);
+
+        // instances get a limited time to resume, and only if they negotiated it
+        smol::block_on(async {
+            assert!(!home.resumed(Duration::from_millis(50)).await);
+            assert!(!away.resumed(Duration::from_secs(60)).await);
+        });
+    }
+
+    #[test]
+    fn protocol_violation() {
+        // an instance that sends something it should not forfeits right away, even if it could resume
+        let (home, _, red) = instance(0, &[Capability::Resume]);
+        let (away, _, black) = instance(1, &[]);
+        let red = script(red, |red| {
+            expect(red, "prompt")?;
+            send(red, "ready")
+        });
+        let black = script(black, |_| Ok(()));
+
+        let game = Game::opening();
+        let live = Live::new((home.id, home.name.clone()), (away.id, away.name.clone()), &game);
+        let mut contest = Contest::new(game, &home, &away, &live, rules(5000));
+        let started = Instant::now();
+        assert_eq!(smol::block_on(contest.run()), Err(home.id));
+        assert!(started.elapsed() < Contest::RESUME_TIME);
+
+        for script in [red, black] {
+            script.join().unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn aborted() {
+        use crate::arbiter::tournament::live::Interrupt;
+
+        // players still get a result when a game is aborted, which is not recorded
+        let (home, _, red) = instance(0, &[Capability::Stop, Capability::Result]);
+        let (away, _, black) = instance(1, &[Capability::Result]);
+        let game = Game::opening();
+        let live = Arc::new(Live::new((0, home.name.clone()), (1, away.name.clone()), &game));
+
+        let abort = live.clone();
+        let red = script(red, move |red| {
+            expect(red, "game")?;
+            send(red, "ready")?;
+            expect(red, "prompt")?;
+            abort.interrupt(Interrupt::Abort);
+            expect(red, "stop")?;
+            expect(red, "result draw aborted")
+        });
+        let black = script(black, |black| {
+            expect(black, "game")?;
+            send(black, "ready")?;
+            expect(black, "result draw aborted")
+        });
+
+        let (record, home, away) = smol::block_on(Instance::compete(home, away, game, rules(5000), &live));
+        assert!(record.is_none());
+        assert!(home.is_some() && away.is_some());
+        for script in [red, black] {
+            script.join().unwrap().unwrap();
+        }
+    }
+}
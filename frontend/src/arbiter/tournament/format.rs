@@ -0,0 +1,168 @@
+// pairings and standings of the tournament formats, with players referred to by their index among the participants
+
+// every participant plays every other once, or twice with colors reversed if double, as (red, black) pairs
+pub fn round_robin(count: usize, double: bool) -> Vec<(usize, usize)> {
+    let mut pairings = Vec::new();
+    for first in 0..count {
+        for second in first + 1..count {
+            // alternate colors so that everyone plays about as many games as red as black
+            let (red, black) = if (first + second) % 2 == 0 {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            pairings.push((red, black));
+            if double {
+                pairings.push((black, red));
+            }
+        }
+    }
+    pairings
+}
+
+// pairs participants with equal or similar points who have not played each other yet, giving red to the one that had
+// it less often, along with the participant that sits out the round if the count is odd
+pub fn swiss(points: &[f64], played: &[Vec<u32>], reds: &[u32], byes: &[u32]) -> (Vec<(usize, usize)>, Option<usize>) {
+    let mut order = (0..points.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| points[b].total_cmp(&points[a]).then(a.cmp(&b)));
+
+    // the lowest ranked participant with the fewest byes sits out
+    let bye = (order.len() % 2 == 1).then(|| {
+        let (position, _) = order
+            .iter()
+            .enumerate()
+            .rev()
+            .min_by_key(|&(_, &player)| byes[player])
+            .unwrap();
+        order.remove(position)
+    });
+
+    let mut pairings = Vec::new();
+    while let Some(first) = order.first().copied() {
+        order.remove(0);
+
+        // prefer the highest ranked opponent not met yet, and a rematch only when everyone has been met
+        let position = (0..order.len())
+            .find(|&position| played[first][order[position]] == 0)
+            .unwrap_or(0);
+        let second = order.remove(position);
+
+        if reds[first] <= reds[second] {
+            pairings.push((first, second));
+        } else {
+            pairings.push((second, first));
+        }
+    }
+
+    (pairings, bye)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Standing {
+    pub player: usize,
+    pub games: u32,
+    pub points: f64,
+    pub sonneborn_berger: f64, // sum of the points of every opponent weighted by the points scored against them
+    pub head_to_head: f64,     // points scored against participants with the same points
+}
+
+// standings from the points each participant scored against each other and the games they played, ordered by
+// points, then sonneborn-berger, then head-to-head
+pub fn standings(scored: &[Vec<f64>], played: &[Vec<u32>], extra: &[f64]) -> Vec<Standing> {
+    let count = scored.len();
+    let points = (0..count)
+        .map(|player| scored[player].iter().sum::<f64>() + extra[player])
+        .collect::<Vec<_>>();
+
+    let mut standings = (0..count)
+        .map(|player| {
+            let mut sonneborn_berger = 0.0;
+            let mut head_to_head = 0.0;
+
+            for other in 0..count {
+                let games = played[player][other];
+                if games == 0 {
+                    continue;
+                }
+
+                sonneborn_berger += scored[player][other] / games as f64 * points[other];
+                if points[other] == points[player] {
+                    head_to_head += scored[player][other];
+                }
+            }
+
+            Standing {
+                player,
+                games: played[player].iter().sum(),
+                points: points[player],
+                sonneborn_berger,
+                head_to_head,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    standings.sort_by(|a, b| {
+        b.points
+            .total_cmp(&a.points)
+            .then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger))
+            .then(b.head_to_head.total_cmp(&a.head_to_head))
+    });
+    standings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_pairings() {
+        let pairings = round_robin(4, false);
+        assert_eq!(pairings.len(), 6);
+        for player in 0..4 {
+            let red = pairings.iter().filter(|&&(red, _)| red == player).count();
+            let black = pairings.iter().filter(|&&(_, black)| black == player).count();
+            assert_eq!(red + black, 3);
+            assert!(red.abs_diff(black) <= 1);
+        }
+
+        let pairings = round_robin(3, true);
+        assert_eq!(pairings.len(), 6);
+        assert!(pairings.contains(&(0, 1)) && pairings.contains(&(1, 0)));
+    }
+
+    #[test]
+    fn swiss_pairings() {
+        let points = [2.0, 2.0, 1.0, 1.0, 0.0];
+        let mut played = vec![vec![0; 5]; 5];
+        played[0][1] = 1;
+        played[1][0] = 1;
+
+        let (pairings, bye) = swiss(&points, &played, &[1, 0, 1, 0, 0], &[0; 5]);
+        assert_eq!(bye, Some(4));
+        assert_eq!(pairings, vec![(0, 2), (1, 3)]);
+
+        let (_, bye) = swiss(&points, &played, &[0; 5], &[0, 0, 0, 0, 1]);
+        assert_eq!(bye, Some(3));
+    }
+
+    #[test]
+    fn standings_order() {
+        // 0 beats 1, 1 beats 2, 2 beats 0, and 3 loses to everyone except for a draw with 2
+        let scored = vec![
+            vec![0.0, 1.0, 0.0, 1.0],
+            vec![0.0, 0.0, 1.0, 1.0],
+            vec![1.0, 0.0, 0.0, 0.5],
+            vec![0.0, 0.0, 0.5, 0.0],
+        ];
+        let played = (0..4)
+            .map(|player| (0..4).map(|other| u32::from(player != other)).collect())
+            .collect::<Vec<Vec<u32>>>();
+
+        let standings = standings(&scored, &played, &[0.0; 4]);
+        let order = standings.iter().map(|standing| standing.player).collect::<Vec<_>>();
+        assert_eq!(order, vec![0, 1, 2, 3]);
+        assert_eq!(standings[0].points, 2.0);
+        assert_eq!(standings[0].sonneborn_berger, 2.5);
+        assert_eq!(standings[2].points, 1.5);
+    }
+}
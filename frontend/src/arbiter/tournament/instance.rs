@@ -1,29 +1,118 @@
+use crate::arbiter::tournament::contest::Contest;
+use crate::arbiter::tournament::live::{Interrupt, Live};
+use crate::arbiter::tournament::record::Record;
+use crate::arbiter::tournament::rules::Rules;
 use crate::arbiter::tournament::PlayerId;
 use crate::line_stream::AsyncLineStream;
-use crate::protocol::{ArbiterMessage, PlayerMessage, Protocol};
-use chinese_chess::game::{Game, Outcome};
+use crate::protocol::{ArbiterMessage, Capabilities, Capability, Conclusion, PlayerMessage, Protocol, Reason, Verdict};
+use chinese_chess::game::Game;
+use chrono::Local;
 use log::{debug, info, trace, warn};
+use smol::channel::{Receiver, Sender};
+use smol::Timer;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 pub struct Instance {
-    id: PlayerId,
+    pub(crate) id: PlayerId,
     pub(crate) name: String,
-    stream: AsyncLineStream,
+    player: String,
+    session: Arc<Session>,
+    capabilities: Capabilities,
+}
+
+// the connection of an instance, which a player that reconnects with the token of the session takes over
+pub struct Session {
+    pub token: String,
+    stream: Mutex<AsyncLineStream>,
+    resumed: (Sender<()>, Receiver<()>),
 }
 
 impl Instance {
-    pub fn new(id: PlayerId, name: String, stream: AsyncLineStream) -> Self {
-        info!("new instance '{name}' registered");
-        Self { id, name, stream }
+    pub fn new(id: PlayerId, player: String, index: u32, session: Arc<Session>, capabilities: Capabilities) -> Self {
+        let name = format!("{player}:{index}");
+        info!("new instance '{name}' registered with capabilities [{capabilities} ]");
+        Self {
+            id,
+            name,
+            player,
+            session,
+            capabilities,
+        }
+    }
+
+    // closes the connection, after which the instance is gone for good
+    pub fn kick(self) {
+        info!("kicked instance '{}'", self.name);
+        self.close();
+    }
+
+    pub fn close(&self) {
+        self.stream().close();
+    }
+
+    fn stream(&self) -> AsyncLineStream {
+        self.session.stream.lock().unwrap().clone()
+    }
+
+    // whether the instance answers a ping before the deadline
+    pub async fn alive(&self, deadline: Instant) -> bool {
+        let token = rand::random::<u32>();
+        if self.send(&ArbiterMessage::Ping { token }).await.is_err() {
+            return false;
+        }
+
+        loop {
+            match self.recv_before(deadline).await {
+                Ok(Some(PlayerMessage::Pong { token: pong })) if pong == token => return true,
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(_) => return false,
+            }
+        }
+    }
+
+    // waits for the player to take over the session with a new connection, which is only possible for instances that
+    // negotiated resume
+    pub async fn resumed(&self, timeout: Duration) -> bool {
+        if !self.supports(Capability::Resume) {
+            return false;
+        }
+
+        let resumed = async { self.session.resumed.1.recv().await.is_ok() };
+        let timeout = async {
+            Timer::after(timeout).await;
+            false
+        };
+        smol::future::or(resumed, timeout).await
     }
 
-    pub async fn compete(home: Instance, away: Instance) -> (Option<Outcome>, Option<Instance>, Option<Instance>) {
-        let game = Game::opening();
+    // forgets any resumption that happened before now, such as while the instance was idle
+    pub fn settle(&self) {
+        while self.session.resumed.1.try_recv().is_ok() {}
+    }
 
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    pub async fn compete(
+        home: Instance,
+        away: Instance,
+        game: Game,
+        rules: Rules,
+        live: &Live,
+    ) -> (Option<Record>, Option<Instance>, Option<Instance>) {
         debug!(
-            "creating game with standard openings for '{}' as red and '{}' as black",
-            home.name, away.name
+            "creating game from {} for '{}' as red and '{}' as black",
+            game.board().fen(),
+            home.name,
+            away.name
         );
 
+        home.settle();
+        away.settle();
+
         // try to initialize the game
         let home_init = Self::compete_init(&game, &home);
         let away_init = Self::compete_init(&game, &away);
@@ -40,43 +129,114 @@ impl Instance {
         trace!("both '{}' and '{}' are ready for game", home.name, away.name);
 
         // home will always be playing red
-        match Self::compete_main(game, &home, &away).await {
-            Ok(outcome) => (Some(outcome), Some(home), Some(away)),
-            Err(id) => {
-                let (result, name) = if id == home.id {
-                    ((Some(Outcome::BlackWon), None, Some(away)), home.name)
-                } else {
-                    assert_eq!(id, away.id);
-                    ((Some(Outcome::RedWon), Some(home), None), away.name)
-                };
+        let start = Local::now();
+        let clock = Instant::now();
+        let mut contest = Contest::new(game, &home, &away, live, rules);
 
+        let run = async { Ok(contest.run().await) };
+        let interrupt = async { Err(live.interrupted().await) };
+        let (conclusion, disconnected) = match smol::future::or(run, interrupt).await {
+            Ok(Ok(conclusion)) => (conclusion, None),
+            Ok(Err(id)) => {
+                let red = id == home.id;
+                let name = if red { &home.name } else { &away.name };
                 warn!("game terminated due to '{name}' resigning from disconnection");
-                result
+                (Conclusion::forfeit(red, Reason::Disconnect), Some(id))
             }
+            Err(Interrupt::Kick(id)) => {
+                let red = id == home.id;
+                let instance = if red { &home } else { &away };
+                warn!("game terminated due to '{}' being kicked", instance.name);
+                instance.close();
+                (Conclusion::forfeit(red, Reason::Disconnect), Some(id))
+            }
+            Err(Interrupt::Abort) => {
+                info!("game between '{}' and '{}' aborted", home.name, away.name);
+                let home_stop = home.notify(&ArbiterMessage::Stop, Capability::Stop);
+                let away_stop = away.notify(&ArbiterMessage::Stop, Capability::Stop);
+                let _ = smol::future::zip(home_stop, away_stop).await;
+
+                // players still learn that the game is over, even though it is not recorded
+                let message = ArbiterMessage::Result {
+                    conclusion: Conclusion::new(Verdict::Draw, Reason::Aborted),
+                };
+                let home_notify = home.notify(&message, Capability::Result);
+                let away_notify = away.notify(&message, Capability::Result);
+                let _ = smol::future::zip(home_notify, away_notify).await;
+                return (None, Some(home), Some(away));
+            }
+        };
+
+        // best effort, as the disconnected instance might not be listening anymore
+        let message = ArbiterMessage::Result { conclusion };
+        let home_notify = home.notify(&message, Capability::Result);
+        let away_notify = away.notify(&message, Capability::Result);
+        let _ = smol::future::zip(home_notify, away_notify).await;
+
+        let record = Record::new(
+            home.player.clone(),
+            away.player.clone(),
+            start,
+            clock.elapsed(),
+            contest.game(),
+            contest.times().to_vec(),
+            conclusion,
+        )
+        .with_analyses(contest.analyses().to_vec());
+
+        match disconnected {
+            None => (Some(record), Some(home), Some(away)),
+            Some(id) if id == home.id => (Some(record), None, Some(away)),
+            Some(_) => (Some(record), Some(home), None),
         }
     }
 
-    async fn recv(&self) -> Result<PlayerMessage, PlayerId> {
-        self.stream
-            .read_line()
-            .await
-            .and_then(|line| {
-                let result = Protocol::decode_player(&line);
-                if result.is_none() {
-                    warn!("failed to decode '{}' message: {line}", self.name);
+    // messages that cannot be decoded are answered with an error and otherwise skipped
+    pub async fn recv(&self) -> Result<PlayerMessage, PlayerId> {
+        loop {
+            let line = self.stream().read_line().await.map_err(|error| {
+                if error.kind() != ErrorKind::UnexpectedEof {
+                    warn!("failed to read from '{}': {error}", self.name);
                 }
-                result
-            })
-            .ok_or(self.id)
+                self.id
+            })?;
+
+            match Protocol::decode_player(&line) {
+                Ok(message) => return Ok(message),
+                Err(error) => {
+                    warn!("failed to decode '{}' message '{line}': {error}", self.name);
+                    self.notify(&ArbiterMessage::Error { error }, Capability::Error).await?;
+                }
+            }
+        }
     }
 
-    async fn send(&self, message: &ArbiterMessage) -> Result<(), PlayerId> {
-        self.stream
+    // returns none if the deadline passed before a message arrived
+    pub async fn recv_before(&self, deadline: Instant) -> Result<Option<PlayerMessage>, PlayerId> {
+        let recv = async { self.recv().await.map(Some) };
+        let timeout = async {
+            Timer::at(deadline).await;
+            Ok(None)
+        };
+        smol::future::or(recv, timeout).await
+    }
+
+    pub async fn send(&self, message: &ArbiterMessage) -> Result<(), PlayerId> {
+        self.stream()
             .write_line(Protocol::encode_arbiter(message))
             .await
             .map_err(|_| self.id)
     }
 
+    // only sends the message if the instance negotiated the capability it belongs to
+    pub async fn notify(&self, message: &ArbiterMessage, capability: Capability) -> Result<(), PlayerId> {
+        if self.supports(capability) {
+            self.send(message).await
+        } else {
+            Ok(())
+        }
+    }
+
     async fn compete_init(game: &Game, instance: &Instance) -> bool {
         let result = async {
             instance.send(&ArbiterMessage::from_game(game)).await?;
@@ -90,59 +250,22 @@ impl Instance {
             true
         }
     }
+}
 
-    async fn compete_main(mut game: Game, home: &Instance, away: &Instance) -> Result<Outcome, PlayerId> {
-        loop {
-            // one move
-            let prompt = async |game: &mut Game, instance: &Instance| -> Result<Option<Outcome>, PlayerId> {
-                if let Some(outcome) = game.outcome() {
-                    debug!(
-                        "game between '{}' and '{}' concluded normally with {outcome}",
-                        home.name, away.name
-                    );
-                    return Ok(Some(outcome));
-                }
-
-                let mv = loop {
-                    let time = 2000;
-                    trace!("prompting '{}' for next move with {time}ms remaining", instance.name);
-                    instance.send(&ArbiterMessage::Prompt { time }).await?;
-
-                    let PlayerMessage::Play { mv } = instance.recv().await? else {
-                        warn!(
-                            "disconnecting '{}' due to unexpected message during game",
-                            instance.name
-                        );
-                        return Err(instance.id);
-                    };
-
-                    let legal = game.can_move(mv);
-                    trace!(
-                        "'{}' requested to play {} move {mv}",
-                        instance.name,
-                        if legal { "legal" } else { "illegal" }
-                    );
-
-                    if legal {
-                        game.make_move(mv);
-                        break mv;
-                    }
-                };
-
-                let message = ArbiterMessage::Update { mv };
-                smol::future::try_zip(home.send(&message), away.send(&message)).await?;
-                Ok(None)
-            };
-
-            // red move
-            if let Some(outcome) = prompt(&mut game, home).await? {
-                return Ok(outcome);
-            }
+impl Session {
+    pub fn new(stream: AsyncLineStream) -> Arc<Self> {
+        let token = format!("{:016x}", rand::random::<u64>());
+        Arc::new(Self {
+            token,
+            stream: Mutex::new(stream),
+            resumed: smol::channel::bounded(1),
+        })
+    }
 
-            // black move
-            if let Some(outcome) = prompt(&mut game, away).await? {
-                return Ok(outcome);
-            }
-        }
+    // replaces the connection, closing the previous one so that anything waiting on it notices right away
+    pub fn resume(&self, stream: AsyncLineStream) {
+        let previous = std::mem::replace(&mut *self.stream.lock().unwrap(), stream);
+        previous.close();
+        let _ = self.resumed.0.try_send(());
     }
 }
@@ -0,0 +1,250 @@
+use crate::arbiter::tournament::PlayerId;
+use crate::protocol::{Analysis, ArbiterMessage, Conclusion, Protocol};
+use chinese_chess::game::Game;
+use chinese_chess::location::Move;
+use smol::channel::{Receiver, Sender};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// a running game as seen from outside of it, through which the console can follow and interrupt it and spectators
+// can watch it
+pub struct Live {
+    pub id: u32,
+    pub red: (PlayerId, String), // player id and instance name
+    pub black: (PlayerId, String),
+    pub start: Instant,
+    progress: Mutex<Progress>,
+    broadcast: Mutex<Broadcast>,
+    aborted: AtomicBool,
+    sender: Sender<Interrupt>,
+    receiver: Receiver<Interrupt>,
+}
+
+#[derive(Copy, Clone)]
+pub struct Progress {
+    pub plies: usize,
+    pub used: [Duration; 2],               // total time spent thinking by red and black
+    pub thinking: Option<(bool, Instant)>, // whether red is to move and since when
+}
+
+// everything a spectator needs to catch up with the game, along with the encoded messages sent to each spectator
+struct Broadcast {
+    start: String, // the encoded starting position
+    position: Game,
+    moves: Vec<Move>,
+    analysis: Option<(bool, Analysis)>, // the latest from either side and whether it came from red
+    spectators: Vec<Sender<String>>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Interrupt {
+    Abort,          // end the game without a result
+    Kick(PlayerId), // disconnect the instance of the player, which forfeits the game
+}
+
+impl Live {
+    pub fn new(red: (PlayerId, String), black: (PlayerId, String), game: &Game) -> Self {
+        static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+        let (sender, receiver) = smol::channel::bounded(1);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            red,
+            black,
+            start: Instant::now(),
+            progress: Mutex::new(Progress {
+                plies: 0,
+                used: [Duration::ZERO; 2],
+                thinking: None,
+            }),
+            broadcast: Mutex::new(Broadcast {
+                start: Protocol::encode_arbiter(&ArbiterMessage::from_game(game)),
+                position: game.clone(),
+                moves: Vec::new(),
+                analysis: None,
+                spectators: Vec::new(),
+            }),
+            aborted: AtomicBool::new(false),
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        *self.progress.lock().unwrap()
+    }
+
+    pub fn begin_turn(&self, red: bool) {
+        self.progress.lock().unwrap().thinking = Some((red, Instant::now()));
+    }
+
+    pub fn end_turn(&self, mv: Move) {
+        let clock = {
+            let mut progress = self.progress.lock().unwrap();
+            if let Some((red, since)) = progress.thinking.take() {
+                progress.used[if red { 0 } else { 1 }] += since.elapsed();
+                progress.plies += 1;
+            }
+            progress.clock()
+        };
+
+        let mut broadcast = self.broadcast.lock().unwrap();
+        broadcast.moves.push(mv);
+        broadcast.position.make_move(mv);
+        broadcast.send(&ArbiterMessage::Update { mv });
+        broadcast.send(&clock);
+    }
+
+    // relays the search of the player to move to spectators
+    pub fn analyze(&self, red: bool, analysis: &Analysis) {
+        let mut broadcast = self.broadcast.lock().unwrap();
+        broadcast.analysis = Some((red, analysis.clone()));
+        let analysis = analysis.clone();
+        broadcast.send(&ArbiterMessage::Analysis { red, analysis });
+    }
+
+    pub fn analysis(&self) -> Option<(bool, Analysis)> {
+        self.broadcast.lock().unwrap().analysis.clone()
+    }
+
+    pub fn position(&self) -> Game {
+        self.broadcast.lock().unwrap().position.clone()
+    }
+
+    // sends the game so far to a new spectator, who then receives every message of the game until it ends
+    pub fn watch(&self) -> Receiver<String> {
+        let (sender, receiver) = smol::channel::unbounded();
+        let clock = self.progress().clock();
+        let mut broadcast = self.broadcast.lock().unwrap();
+
+        let watching = ArbiterMessage::Watching {
+            id: self.id,
+            red: self.red.1.clone(),
+            black: self.black.1.clone(),
+        };
+        let _ = sender.try_send(Protocol::encode_arbiter(&watching));
+        let _ = sender.try_send(broadcast.start.clone());
+        for &mv in &broadcast.moves {
+            let _ = sender.try_send(Protocol::encode_arbiter(&ArbiterMessage::Update { mv }));
+        }
+        let _ = sender.try_send(Protocol::encode_arbiter(&clock));
+        if let Some((red, analysis)) = broadcast.analysis.clone() {
+            let _ = sender.try_send(Protocol::encode_arbiter(&ArbiterMessage::Analysis { red, analysis }));
+        }
+        broadcast.spectators.push(sender);
+        receiver
+    }
+
+    // tells every spectator how the game ended, or that it is gone if it ended without a result
+    pub fn finish(&self, conclusion: Option<Conclusion>) {
+        let mut broadcast = self.broadcast.lock().unwrap();
+        match conclusion {
+            Some(conclusion) => broadcast.send(&ArbiterMessage::Result { conclusion }),
+            None => broadcast.send(&ArbiterMessage::Missing { id: self.id }),
+        }
+        broadcast.spectators.clear();
+    }
+
+    // returns false if the game was already interrupted
+    pub fn interrupt(&self, interrupt: Interrupt) -> bool {
+        let sent = self.sender.try_send(interrupt).is_ok();
+        if sent && matches!(interrupt, Interrupt::Abort) {
+            self.aborted.store(true, Ordering::Relaxed);
+        }
+        sent
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    // completes once the game is interrupted
+    pub async fn interrupted(&self) -> Interrupt {
+        self.receiver.recv().await.unwrap_or(Interrupt::Abort)
+    }
+}
+
+impl Progress {
+    fn clock(&self) -> ArbiterMessage {
+        let [red, black] = self.used.map(|used| used.as_millis() as u32);
+        ArbiterMessage::Clock { red, black }
+    }
+}
+
+impl Broadcast {
+    // spectators that went away are dropped
+    fn send(&mut self, message: &ArbiterMessage) {
+        let line = Protocol::encode_arbiter(message);
+        self.spectators
+            .retain(|spectator| spectator.try_send(line.clone()).is_ok());
+    }
+}
+
+impl Display for Live {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let progress = self.progress();
+        let [red, black] = progress.used.map(|used| used.as_secs_f32());
+        write!(
+            f,
+            "#{} {} vs. {} - ply {} red {red:.1}s black {black:.1}s",
+            self.id, self.red.1, self.black.1, progress.plies
+        )?;
+
+        if let Some((red, since)) = progress.thinking {
+            let side = if red { "red" } else { "black" };
+            write!(f, ", {side} thinking for {:.1}s", since.elapsed().as_secs_f32())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Reason, Verdict};
+
+    #[test]
+    fn spectators_catch_up() {
+        let live = Live::new((0, "a:0".to_owned()), (1, "b:0".to_owned()), &Game::opening());
+        let received = |spectator: &Receiver<String>| {
+            let messages = std::iter::from_fn(|| spectator.try_recv().ok());
+            messages
+                .map(|message| message.split_whitespace().take(2).collect::<Vec<_>>().join(" "))
+                .collect::<Vec<_>>()
+        };
+
+        live.begin_turn(true);
+        live.end_turn("h2e2".parse().unwrap());
+        let analysis = Protocol::decode_analysis("depth 3 score 20 pv h9g7".split(' ')).unwrap();
+        live.analyze(false, &analysis);
+
+        // a spectator joining midway is sent the game so far along with the latest analysis
+        let spectator = live.watch();
+        let start = format!("game {}", Game::opening().board().fen());
+        assert_eq!(
+            received(&spectator),
+            [
+                &format!("watching {}", live.id),
+                &start,
+                "update h2e2",
+                "clock 0",
+                "analysis black"
+            ]
+        );
+
+        live.begin_turn(false);
+        live.end_turn("h9g7".parse().unwrap());
+        live.finish(Some(Conclusion::new(Verdict::Draw, Reason::Agreement)));
+        assert_eq!(received(&spectator), ["update h9g7", "clock 0", "result draw"]);
+        assert!(spectator.is_closed());
+        assert_eq!(live.progress().plies, 2);
+        assert_eq!(live.position().history().len(), 2);
+
+        // only the first interrupt is delivered
+        assert!(live.interrupt(Interrupt::Abort));
+        assert!(!live.interrupt(Interrupt::Kick(0)));
+        assert!(live.aborted());
+    }
+}
@@ -1,13 +1,36 @@
-use crate::arbiter::tournament::status::Status;
-use crate::line_stream::AsyncLineStream;
-use log::debug;
+use crate::arbiter::tournament::access::Access;
+use crate::arbiter::tournament::format::Standing;
+use crate::arbiter::tournament::instance::Session;
+use crate::arbiter::tournament::live::{Interrupt, Live};
+use crate::arbiter::tournament::openings::Openings;
+use crate::arbiter::tournament::record::Record;
+use crate::arbiter::tournament::rules::Rules;
+use crate::arbiter::tournament::scheduler::{Limits, Scheduler};
+use crate::arbiter::tournament::statistics::Sprt;
+use crate::arbiter::tournament::status::{Score, Status};
+use crate::protocol::Capabilities;
+use chinese_chess::game::Game;
+use log::{debug, info, warn};
 use player::Player;
+use smol::Timer;
 use std::collections::HashMap;
 use std::ops::Div;
-use std::sync::{Arc, RwLock, Weak};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
 
-mod instance;
+pub mod access;
+pub mod adjudication;
+mod contest;
+pub mod format;
+pub mod instance;
+pub mod live;
+mod openings;
 mod player;
+pub mod record;
+pub mod rules;
+pub mod scheduler;
+pub mod statistics;
 pub mod status;
 
 type PlayerId = usize;
@@ -16,15 +39,71 @@ pub struct Tournament {
     this: Weak<RwLock<Self>>,
     ids: HashMap<String, PlayerId>,
     players: Vec<Arc<RwLock<Player>>>,
+    sessions: HashMap<String, (PlayerId, Weak<Session>)>, // sessions of connected instances by their token
+    rules: Rules,
+    access: Access,
+    storage: Option<PathBuf>,
+    export: Option<PathBuf>,
+    records: Mutex<Vec<Record>>, // every finished game, including ones loaded from storage
+    tests: Mutex<Vec<Test>>,
+    participants: Vec<PlayerId>, // players of the most recent round-robin, gauntlet or swiss event
+    swiss: Mutex<Option<Swiss>>,
+    games: Mutex<Vec<Arc<Live>>>, // games that are running right now
+    paused: bool,                 // whether new games are held back
+    scheduler: Mutex<Scheduler>,
+    expected: Vec<(PlayerId, u32)>, // instance counts to wait for before the first game starts
+    openings: Mutex<Openings>,      // positions games start from, handed out per pairing
+}
+
+// an open-ended match between two players that goes on until its sprt reaches a decision
+struct Test {
+    player: PlayerId,
+    against: PlayerId,
+    sprt: Sprt,
+    start: Score, // games between the players from before the test, which it does not count
+}
+
+struct Swiss {
+    players: Vec<PlayerId>,
+    rounds: u32,
+    round: u32,
+    reds: Vec<u32>,         // games played as red by each player
+    byes: Vec<u32>,         // rounds each player sat out, which are worth a win
+    pairings: Vec<Pairing>, // games of the current round
+}
+
+// a game of a swiss round, which is finished once it has a result or will not be played anymore
+struct Pairing {
+    red: PlayerId,
+    black: PlayerId,
+    game: Option<u32>, // id of the game that is running for it
+    finished: bool,
 }
 
 impl Tournament {
-    pub fn new() -> Arc<RwLock<Self>> {
+    const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+    const PONG_TIME: Duration = Duration::from_secs(3);
+
+    pub fn new(rules: Rules) -> Arc<RwLock<Self>> {
         Arc::new_cyclic(|weak| {
             RwLock::new(Self {
                 this: weak.clone(),
                 ids: HashMap::new(),
                 players: Vec::new(),
+                sessions: HashMap::new(),
+                rules,
+                access: Access::default(),
+                storage: None,
+                export: None,
+                records: Mutex::new(Vec::new()),
+                tests: Mutex::new(Vec::new()),
+                participants: Vec::new(),
+                swiss: Mutex::new(None),
+                games: Mutex::new(Vec::new()),
+                paused: false,
+                scheduler: Mutex::new(Scheduler::default()),
+                expected: Vec::new(),
+                openings: Mutex::new(Openings::default()),
             })
         })
     }
@@ -40,23 +119,593 @@ impl Tournament {
         })
     }
 
-    pub fn join(&mut self, name: &str, stream: AsyncLineStream) {
+    // loads the results of all games stored in the file and appends every game finished from now on
+    pub fn open(&mut self, path: &Path) -> std::io::Result<()> {
+        let (records, invalid) = Record::load(path)?;
+        if invalid > 0 {
+            warn!("skipped {invalid} invalid game records in '{}'", path.display());
+        }
+
+        for record in &records {
+            let red = self.get_or_create_id(&record.red);
+            let black = self.get_or_create_id(&record.black);
+            self.players[red].write().unwrap().record(black, record);
+        }
+
+        info!("loaded {} game records from '{}'", records.len(), path.display());
+        self.records.lock().unwrap().extend(records);
+        self.storage = Some(path.to_owned());
+        Ok(())
+    }
+
+    // appends every game finished from now on to a pgn file
+    pub fn export(&mut self, path: &Path) {
+        self.export = Some(path.to_owned());
+    }
+
+    fn store(&self, record: &Record) {
+        self.records.lock().unwrap().push(record.clone());
+
+        if let Some(path) = &self.storage
+            && let Err(error) = record.append(path)
+        {
+            warn!("failed to store game record in '{}': {error}", path.display());
+        }
+
+        if let Some(path) = &self.export
+            && let Err(error) = record.append_pgn(path)
+        {
+            warn!("failed to export game record to '{}': {error}", path.display());
+        }
+    }
+
+    pub fn join(&mut self, name: &str, session: Arc<Session>, capabilities: Capabilities) {
         let id = self.get_or_create_id(name);
 
+        self.sessions.retain(|_, (_, session)| session.strong_count() > 0);
+        self.sessions
+            .insert(session.token.clone(), (id, Arc::downgrade(&session)));
+
         {
             let mut player = self.players[id].write().unwrap();
-            player.create_instance(stream);
+            player.create_instance(session, capabilities);
+        }
+
+        let connected = |&(id, count): &(PlayerId, u32)| self.players[id].read().unwrap().instance_count() >= count;
+        if !self.expected.is_empty() && self.expected.iter().all(connected) {
+            info!("all expected instances connected");
+            self.expected.clear();
         }
 
         self.match_all();
     }
 
+    // the session of a connected instance of the player, which a reconnecting player can take over with the token
+    pub fn session(&self, name: &str, token: &str) -> Option<Arc<Session>> {
+        let &(id, ref session) = self.sessions.get(token)?;
+        if self.ids.get(name) != Some(&id) {
+            return None;
+        }
+        session.upgrade()
+    }
+
+    // pings idle instances every so often, evicting the ones that do not answer in time
+    pub async fn monitor(this: Arc<RwLock<Self>>) {
+        loop {
+            Timer::after(Self::HEALTH_INTERVAL).await;
+            let players = this.read().unwrap().players.clone();
+
+            let mut checks = Vec::new();
+            for player in players {
+                let idle = player.write().unwrap().take_idle();
+                let deadline = Instant::now() + Self::PONG_TIME;
+
+                for instance in idle {
+                    let (this, player) = (this.clone(), player.clone());
+                    checks.push(smol::spawn(async move {
+                        if !instance.alive(deadline).await {
+                            warn!("evicting instance '{}' as it did not answer a ping", instance.name);
+                            instance.kick();
+                            return;
+                        }
+
+                        player.write().unwrap().restore(instance);
+                        this.read().unwrap().match_all();
+                    }));
+                }
+            }
+
+            for check in checks {
+                check.await;
+            }
+        }
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    // applies to games started from now on
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    // applies to connections made from now on, instances that are already connected stay
+    pub fn set_access(&mut self, access: Access) {
+        match &access.secret {
+            Some(_) => info!(
+                "requiring keys from every connection, {} of them player keys",
+                access.keys.len()
+            ),
+            None => info!("requiring keys from instances of {} players", access.keys.len()),
+        }
+        self.access = access;
+    }
+
+    // games between two players cycle through the openings, with each opening played by both players as red
+    pub fn set_openings(&mut self, openings: Vec<Game>) {
+        info!("using {} openings", openings.len());
+        *self.openings.get_mut().unwrap() = Openings::new(openings);
+    }
+
+    // holds back all games until the player has connected this many instances
+    pub fn expect(&mut self, name: &str, count: u32) {
+        let id = self.get_or_create_id(name);
+        if self.players[id].read().unwrap().instance_count() < count {
+            info!("waiting for {count} instances of '{name}'");
+            self.expected.retain(|&(other, _)| other != id);
+            self.expected.push((id, count));
+        }
+    }
+
     #[must_use]
     pub fn enqueue(&'_ mut self, name: &str) -> Queue<'_> {
         let id = self.get_or_create_id(name);
         Queue::new(self, id)
     }
 
+    // removes the matches of the player that have not started yet, against the opponents or everyone if none are given
+    pub fn dequeue(&mut self, name: &str, against: &[String]) -> Option<u32> {
+        let id = self.ids.get(name).copied()?;
+        let opponents = if against.is_empty() {
+            (0..self.players.len()).filter(|&other| other != id).collect()
+        } else {
+            against
+                .iter()
+                .filter_map(|name| self.ids.get(name).copied())
+                .collect::<Vec<_>>()
+        };
+
+        let mut count = 0;
+        for other in opponents.into_iter().filter(|&other| other != id) {
+            count += self.players[id].write().unwrap().dequeue(other);
+            count += self.players[other].write().unwrap().dequeue(id);
+        }
+
+        info!("dequeued {count} matches of '{name}'");
+        self.update_swiss();
+        Some(count)
+    }
+
+    // ends a running game without a result, returns false if there is no such game
+    pub fn abort(&self, id: u32) -> bool {
+        let games = self.games.lock().unwrap();
+        let Some(game) = games.iter().find(|game| game.id == id) else {
+            return false;
+        };
+
+        game.interrupt(Interrupt::Abort)
+    }
+
+    // disconnects every instance of a player or a single instance given its name such as robot:0, with playing
+    // instances forfeiting their games, returns how many instances were kicked
+    pub fn kick(&self, name: &str) -> usize {
+        let (id, instance) = match self.ids.get(name) {
+            Some(&id) => (id, None),
+            None => match name.rsplit_once(':').and_then(|(player, _)| self.ids.get(player)) {
+                Some(&id) => (id, Some(name)),
+                None => return 0,
+            },
+        };
+
+        let mut count = self.players[id].write().unwrap().kick(instance);
+        for game in self.games.lock().unwrap().iter() {
+            for (player, current) in [&game.red, &game.black] {
+                if *player == id && instance.is_none_or(|name| name == current) && game.interrupt(Interrupt::Kick(id)) {
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.scheduler.lock().unwrap().limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        info!("limited running games to {limits}");
+        self.scheduler.get_mut().unwrap().limits = limits;
+        self.match_all();
+    }
+
+    // players with higher priority have their games started first, with every player starting at zero
+    pub fn prioritize(&mut self, name: &str, priority: i32) {
+        let id = self.get_or_create_id(name);
+        info!("set priority of player '{name}' to {priority}");
+        self.scheduler.get_mut().unwrap().set_priority(id, priority);
+        self.match_all();
+    }
+
+    // holds back new games until resumed, games that already started are played to the end
+    pub fn pause(&mut self) {
+        info!("paused scheduling of new games");
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        info!("resumed scheduling of new games");
+        self.paused = false;
+        self.match_all();
+    }
+
+    pub fn iter_games(&self) -> impl Iterator<Item = Arc<Live>> {
+        self.games.lock().unwrap().clone().into_iter()
+    }
+
+    pub fn game(&self, id: u32) -> Option<Arc<Live>> {
+        self.games.lock().unwrap().iter().find(|game| game.id == id).cloned()
+    }
+
+    // the home and away player names of every pair with queued matches along with how many
+    pub fn iter_queued(&self) -> impl Iterator<Item = (String, String, u32)> {
+        let queued = self
+            .players
+            .iter()
+            .flat_map(|player| {
+                let player = player.read().unwrap();
+                player
+                    .iter_queued()
+                    .map(|(away, queued)| (player.name.clone(), away, queued))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        queued
+            .into_iter()
+            .map(|(home, away, queued)| (home, self.players[away].read().unwrap().name.clone(), queued))
+    }
+
+    // forgets the finished games of the players, or of everyone if none are given, only in memory so that the stored
+    // records and the pages built from them keep every game, which is all loaded again on the next start
+    pub fn reset_status(&mut self, names: &[String]) {
+        if names.is_empty() {
+            self.players
+                .iter()
+                .for_each(|player| player.write().unwrap().reset_status());
+        }
+
+        for name in names {
+            match self.ids.get(name) {
+                Some(&id) => self.players[id].write().unwrap().reset_status(),
+                None => warn!("cannot reset unknown player '{name}'"),
+            }
+        }
+
+        // running tests count from the reset on
+        for test in self.tests.lock().unwrap().iter_mut() {
+            test.start = self.pair_status(test.player, test.against).score;
+        }
+    }
+
+    // queues every player against every other, once or twice with colors reversed
+    pub fn round_robin(&mut self, names: &[String], double: bool) {
+        self.participants = self.participant_ids(names);
+        for (red, black) in format::round_robin(self.participants.len(), double) {
+            let (red, black) = (self.participants[red], self.participants[black]);
+            self.players[red].write().unwrap().enqueue(black, 1);
+        }
+
+        info!("started round-robin between {} players", self.participants.len());
+        self.match_all();
+    }
+
+    // queues the player against every opponent, without the opponents playing each other
+    pub fn gauntlet(&mut self, name: &str, opponents: &[String], count: u32) {
+        let mut names = vec![name.to_owned()];
+        names.extend(opponents.iter().filter(|opponent| *opponent != name).cloned());
+        self.participants = self.participant_ids(&names);
+
+        let mut queue = self.enqueue(name);
+        for opponent in names.into_iter().skip(1) {
+            queue = queue.against(opponent, count, None);
+        }
+
+        drop(queue);
+        info!(
+            "started gauntlet of '{name}' against {} players",
+            self.participants.len() - 1
+        );
+    }
+
+    // plays rounds in which players with similar points are paired, starting the next round once all of its games
+    // are finished
+    pub fn swiss(&mut self, names: &[String], rounds: u32) -> Result<(), String> {
+        if self
+            .swiss
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|swiss| swiss.round < swiss.rounds)
+        {
+            return Err("a swiss event is already running".to_owned());
+        }
+
+        let players = self.participant_ids(names);
+        if players.len() < 2 || rounds == 0 {
+            return Err("a swiss event needs at least two players and one round".to_owned());
+        }
+
+        self.participants = players.clone();
+        let mut swiss = Swiss {
+            reds: vec![0; players.len()],
+            byes: vec![0; players.len()],
+            players,
+            rounds,
+            round: 0,
+            pairings: Vec::new(),
+        };
+
+        info!(
+            "started swiss between {} players over {rounds} rounds",
+            swiss.players.len()
+        );
+        self.next_round(&mut swiss);
+        *self.swiss.lock().unwrap() = Some(swiss);
+        self.match_all();
+        Ok(())
+    }
+
+    // the standings of the players, or of the most recent event if none are given
+    pub fn standings(&self, names: &[String]) -> Vec<(String, Standing)> {
+        let players = if !names.is_empty() {
+            names.iter().filter_map(|name| self.ids.get(name).copied()).collect()
+        } else if !self.participants.is_empty() {
+            self.participants.clone()
+        } else {
+            (0..self.players.len()).collect()
+        };
+
+        let swiss = self.swiss.lock().unwrap();
+        let byes = players
+            .iter()
+            .map(|player| match swiss.as_ref() {
+                Some(swiss) => swiss
+                    .players
+                    .iter()
+                    .zip(&swiss.byes)
+                    .find(|(id, _)| *id == player)
+                    .map_or(0.0, |(_, &byes)| byes as f64),
+                None => 0.0,
+            })
+            .collect::<Vec<_>>();
+
+        let (scored, played) = self.results(&players);
+        format::standings(&scored, &played, &byes)
+            .into_iter()
+            .map(|standing| {
+                let name = self.players[players[standing.player]].read().unwrap().name.clone();
+                (name, standing)
+            })
+            .collect()
+    }
+
+    fn participant_ids(&mut self, names: &[String]) -> Vec<PlayerId> {
+        let mut ids = Vec::new();
+        for name in names {
+            let id = self.get_or_create_id(name);
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    // points scored by each player against each other and the number of games between them
+    fn results(&self, players: &[PlayerId]) -> (Vec<Vec<f64>>, Vec<Vec<u32>>) {
+        let mut scored = vec![vec![0.0; players.len()]; players.len()];
+        let mut played = vec![vec![0; players.len()]; players.len()];
+
+        for (index, &player) in players.iter().enumerate() {
+            for (other_index, &other) in players.iter().enumerate() {
+                if player != other {
+                    let score = self.pair_status(player, other).score;
+                    scored[index][other_index] = score.win as f64 + score.draw as f64 / 2.0;
+                    played[index][other_index] = score.total();
+                }
+            }
+        }
+
+        (scored, played)
+    }
+
+    // starts the next swiss round once every game of the current one is finished
+    fn update_swiss(&self) {
+        let mut lock = self.swiss.lock().unwrap();
+        let Some(swiss) = lock.as_mut() else { return };
+        if swiss.pairings.is_empty() {
+            return;
+        }
+
+        // games that are neither queued nor running, such as the dequeued ones, are not going to be played
+        for pairing in swiss.pairings.iter_mut().filter(|pairing| pairing.game.is_none()) {
+            let status = self.players[pairing.red].read().unwrap().status(pairing.black);
+            pairing.finished |= status.queued == 0 && status.running == 0;
+        }
+
+        if !swiss.pairings.iter().all(|pairing| pairing.finished) {
+            return;
+        }
+
+        info!("swiss round {} of {} finished", swiss.round, swiss.rounds);
+        if swiss.round < swiss.rounds {
+            self.next_round(swiss);
+        } else {
+            swiss.pairings.clear();
+            info!("swiss event finished, see standings for the results");
+        }
+    }
+
+    fn next_round(&self, swiss: &mut Swiss) {
+        let (scored, played) = self.results(&swiss.players);
+        let points = scored
+            .iter()
+            .zip(&swiss.byes)
+            .map(|(scored, &byes)| scored.iter().sum::<f64>() + byes as f64)
+            .collect::<Vec<_>>();
+
+        let (pairings, bye) = format::swiss(&points, &played, &swiss.reds, &swiss.byes);
+        swiss.round += 1;
+        swiss.pairings.clear();
+
+        if let Some(bye) = bye {
+            swiss.byes[bye] += 1;
+            let name = &self.players[swiss.players[bye]].read().unwrap().name;
+            info!("'{name}' sits out swiss round {}", swiss.round);
+        }
+
+        for (red, black) in pairings {
+            swiss.reds[red] += 1;
+            let (red, black) = (swiss.players[red], swiss.players[black]);
+            swiss.pairings.push(Pairing {
+                red,
+                black,
+                game: None,
+                finished: false,
+            });
+
+            let opponent = self.players[black].read().unwrap().name.clone();
+            let mut player = self.players[red].write().unwrap();
+            info!(
+                "swiss round {} pairs '{}' as red against '{opponent}'",
+                swiss.round, player.name
+            );
+            player.enqueue(black, 1);
+        }
+    }
+
+    // assigns a game that just started to the swiss round if it is one of its games that is still waiting to be played
+    fn start_swiss(&self, red: PlayerId, black: PlayerId, game: u32) {
+        let mut lock = self.swiss.lock().unwrap();
+        let Some(swiss) = lock.as_mut() else { return };
+        let waiting = |pairing: &&mut Pairing| {
+            pairing.red == red && pairing.black == black && pairing.game.is_none() && !pairing.finished
+        };
+
+        if let Some(pairing) = swiss.pairings.iter_mut().find(waiting) {
+            pairing.game = Some(game);
+        }
+    }
+
+    // a swiss game that ended is finished, even without a result such as when it was aborted, unless it is queued to
+    // be played again
+    fn end_swiss(&self, game: u32, requeued: bool) {
+        let mut lock = self.swiss.lock().unwrap();
+        let Some(swiss) = lock.as_mut() else { return };
+
+        if let Some(pairing) = swiss.pairings.iter_mut().find(|pairing| pairing.game == Some(game)) {
+            pairing.game = None;
+            pairing.finished = !requeued;
+        }
+    }
+
+    // starts an sprt of the player against another, which keeps games between them queued until it reaches a decision
+    pub fn sprt(&mut self, name: &str, against: &str, sprt: Sprt) -> Result<(), String> {
+        if name == against {
+            return Err(format!("cannot test '{name}' against itself"));
+        }
+
+        let player = self.get_or_create_id(name);
+        let against = self.get_or_create_id(against);
+        let (lower, upper) = sprt.bounds();
+        info!(
+            "starting sprt of '{name}' against '{}' with {sprt}, bounds [{lower:.3}, {upper:.3}]",
+            self.players[against].read().unwrap().name
+        );
+
+        let start = self.pair_status(player, against).score;
+        self.tests.lock().unwrap().push(Test {
+            player,
+            against,
+            sprt,
+            start,
+        });
+        self.update_tests();
+        self.match_all();
+        Ok(())
+    }
+
+    // the player and opponent names of every running sprt along with the score of the games played under it
+    pub fn iter_tests(&self) -> impl Iterator<Item = (String, String, Sprt, Score)> {
+        let tests = self.tests.lock().unwrap();
+        let result = tests
+            .iter()
+            .map(|test| {
+                let player = self.players[test.player].read().unwrap().name.clone();
+                let against = self.players[test.against].read().unwrap().name.clone();
+                (player, against, test.sprt, self.test_score(test))
+            })
+            .collect::<Vec<_>>();
+        result.into_iter()
+    }
+
+    // concludes tests that reached a decision and keeps games queued for the others
+    fn update_tests(&self) {
+        self.tests.lock().unwrap().retain(|test| {
+            let score = self.test_score(test);
+            let name = self.players[test.player].read().unwrap().name.clone();
+            let against = self.players[test.against].read().unwrap().name.clone();
+
+            if let Some(decision) = test.sprt.decide(&score) {
+                let llr = test.sprt.llr(&score);
+                info!(
+                    "sprt of '{name}' against '{against}' {decision} after {} games with llr {llr:.3}: {score}",
+                    score.total(),
+                );
+
+                self.players[test.player].write().unwrap().dequeue(test.against);
+                self.players[test.against].write().unwrap().dequeue(test.player);
+                return false;
+            }
+
+            // queue games in pairs so that both colors are played equally often
+            if self.pair_status(test.player, test.against).queued == 0 {
+                self.players[test.player].write().unwrap().enqueue(test.against, 1);
+                self.players[test.against].write().unwrap().enqueue(test.player, 1);
+            }
+
+            true
+        });
+    }
+
+    fn test_score(&self, test: &Test) -> Score {
+        self.pair_status(test.player, test.against).score.since(&test.start)
+    }
+
+    // all games between the two players from the perspective of the first
+    fn pair_status(&self, player: PlayerId, against: PlayerId) -> Status {
+        let mut status = self.players[player].read().unwrap().status(against);
+        let mut other = self.players[against].read().unwrap().status(player);
+        other.negate();
+        status.merge(&other);
+        status
+    }
+
     pub fn status(&self, name: &str) -> Option<impl Iterator<Item = (String, Status)>> {
         let id = self.ids.get(name).copied()?;
 
@@ -80,7 +729,7 @@ impl Tournament {
                     continue;
                 }
 
-                status.score.negate();
+                status.negate();
                 result
                     .entry(player.name.clone())
                     .and_modify(|current| current.merge(&status))
@@ -91,11 +740,78 @@ impl Tournament {
         Some(result.into_iter())
     }
 
+    // ratings of all players relative to the average player together with their total scores, from best to worst
+    pub fn ratings(&self) -> Vec<(String, f64, Score)> {
+        let mut results = Vec::new();
+        let mut scores = vec![Score::new(); self.players.len()];
+
+        for (id, player) in self.players.iter().enumerate() {
+            for (other_id, mut status) in player.read().unwrap().iter_status() {
+                results.push((id, other_id, status.score));
+                scores[id].merge(&status.score);
+                status.negate();
+                scores[other_id].merge(&status.score);
+            }
+        }
+
+        let ratings = statistics::ratings(self.players.len(), &results);
+        let mut result = self
+            .players
+            .iter()
+            .zip(ratings)
+            .zip(scores)
+            .map(|((player, rating), score)| (player.read().unwrap().name.clone(), rating, score))
+            .collect::<Vec<_>>();
+
+        result.sort_by(|(_, a, _), (_, b, _)| b.total_cmp(a));
+        result
+    }
+
     pub fn iter_players(&self) -> impl Iterator<Item = &String> {
         self.ids.keys()
     }
 
+    // player and instance names of every connected instance along with the game it is playing, if any
+    pub fn instances(&self) -> Vec<(String, String, Option<u32>)> {
+        let mut instances = Vec::new();
+        for player in &self.players {
+            let player = player.read().unwrap();
+            let idle = player
+                .iter_idle()
+                .map(|name| (player.name.clone(), name.to_owned(), None));
+            instances.extend(idle);
+        }
+
+        for game in self.iter_games() {
+            for (id, name) in [&game.red, &game.black] {
+                let player = self.players[*id].read().unwrap().name.clone();
+                instances.push((player, name.clone(), Some(game.id)));
+            }
+        }
+        instances
+    }
+
+    // finished games starting from this index in the order they finished, at most count of them
+    pub fn records(&self, from: usize, count: usize) -> Vec<Record> {
+        let records = self.records.lock().unwrap();
+        records.iter().skip(from).take(count).cloned().collect()
+    }
+
+    pub fn record_count(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
     fn match_all(&self) {
+        if self.paused {
+            debug!("not matching players as scheduling is paused");
+            return;
+        }
+
+        if !self.expected.is_empty() {
+            debug!("not matching players as expected instances have not all connected");
+            return;
+        }
+
         debug!("attempting to match all available players");
 
         let mut candidates = self
@@ -112,32 +828,65 @@ impl Tournament {
             })
             .collect::<Vec<_>>();
 
+        let mut scheduler = self.scheduler.lock().unwrap();
         loop {
-            candidates.sort_unstable_by_key(|(_, _, queued)| *queued);
+            scheduler.order(&mut candidates);
+            let running = self
+                .games
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|game| (game.red.0, game.black.0))
+                .collect::<Vec<_>>();
 
-            let mut candidates = candidates.iter_mut().rev().filter_map(|(home, away, queued)| {
-                if *queued == 0 {
+            let mut candidates = candidates.iter_mut().filter_map(|(home, away, queued)| {
+                if *queued == 0 || !scheduler.allows(&running, *home, *away) {
                     return None;
                 }
 
-                let home = self.players[*home].clone();
-                let away = self.players[*away].clone();
-                let future = Player::play(home, away)?;
+                let pairing = (*home, *away);
+                let mut openings = self.openings.lock().unwrap();
+                let opening = openings.start(*home, *away);
+                let game = openings.game(opening);
+                drop(openings);
+
+                let players = (self.players[*home].clone(), self.players[*away].clone());
+                let Some((live, future)) = Player::play(players.0, players.1, game, self.rules) else {
+                    self.openings.lock().unwrap().restore(pairing.0, pairing.1, opening);
+                    return None;
+                };
 
                 *queued -= 1;
                 let this = self.this.upgrade().unwrap();
+                self.games.lock().unwrap().push(live.clone());
+                self.start_swiss(pairing.0, pairing.1, live.id);
 
-                Some(async move {
-                    future.await;
-                    this.read().unwrap().match_all();
-                })
+                Some((pairing, async move {
+                    let record = future.await;
+                    let this = this.read().unwrap();
+                    this.games.lock().unwrap().retain(|game| !Arc::ptr_eq(game, &live));
+
+                    // a game that did not start is queued again, and played from the same opening
+                    let requeued = record.is_none() && !live.aborted();
+                    match record {
+                        Some(record) => this.store(&record),
+                        None if requeued => this.openings.lock().unwrap().restore(pairing.0, pairing.1, opening),
+                        None => {}
+                    }
+                    this.end_swiss(live.id, requeued);
+                    this.update_tests();
+                    this.update_swiss();
+                    this.match_all();
+                }))
             });
 
-            if let Some(future) = candidates.next() {
-                smol::spawn(future).detach();
-            } else {
+            // only one game starts at a time as every start changes the order and the running games
+            let Some(((home, away), future)) = candidates.next() else {
                 break;
-            }
+            };
+            drop(candidates);
+            scheduler.start(home, away);
+            smol::spawn(future).detach();
         }
     }
 }
@@ -185,3 +934,107 @@ impl Drop for Queue<'_> {
         self.tournament.match_all();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_by_token() {
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let _client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stream = smol::net::TcpStream::try_from(listener.accept().unwrap().0).unwrap();
+        let session = Session::new(crate::line_stream::AsyncLineStream::new(stream));
+
+        let tournament = Tournament::new(Rules::new());
+        let mut tournament = tournament.write().unwrap();
+        tournament.join("a", session.clone(), Capabilities::none());
+
+        // only the player the session belongs to can take it over
+        let taken = tournament.session("a", &session.token);
+        assert!(taken.is_some_and(|taken| Arc::ptr_eq(&taken, &session)));
+        assert!(tournament.session("b", &session.token).is_none());
+        assert!(tournament.session("a", "0000000000000000").is_none());
+
+        // and only while it is still connected
+        let token = session.token.clone();
+        tournament.kick("a");
+        drop(session);
+        assert!(tournament.session("a", &token).is_none());
+    }
+
+    #[test]
+    fn swiss_rounds_end_without_results() {
+        let tournament = Tournament::new(Rules::new());
+        let mut tournament = tournament.write().unwrap();
+        let names = ["a".to_owned(), "b".to_owned()];
+        tournament.swiss(&names, 2).unwrap();
+        let round = |tournament: &Tournament| tournament.swiss.lock().unwrap().as_ref().unwrap().round;
+        let pairing = |tournament: &Tournament| {
+            let swiss = tournament.swiss.lock().unwrap();
+            let pairing = &swiss.as_ref().unwrap().pairings[0];
+            (pairing.red, pairing.black)
+        };
+
+        // a dequeued game is never going to be played
+        assert_eq!(round(&tournament), 1);
+        assert_eq!(tournament.dequeue("a", &[]), Some(1));
+        assert_eq!(round(&tournament), 2);
+
+        // a game that failed to start is played again, while an aborted one counts as finished
+        let (red, black) = pairing(&tournament);
+        tournament.start_swiss(red, black, 7);
+        tournament.end_swiss(7, true);
+        tournament.update_swiss();
+        assert_eq!(tournament.swiss.lock().unwrap().as_ref().unwrap().pairings.len(), 1);
+
+        tournament.start_swiss(red, black, 8);
+        tournament.end_swiss(8, false);
+        tournament.update_swiss();
+        assert!(tournament.swiss.lock().unwrap().as_ref().unwrap().pairings.is_empty());
+        assert_eq!(round(&tournament), 2);
+    }
+
+    #[test]
+    fn sprt_counts_only_its_own_games() {
+        use crate::protocol::{Conclusion, Reason, Verdict};
+
+        let tournament = Tournament::new(Rules::new());
+        let mut tournament = tournament.write().unwrap();
+        let (a, b) = (tournament.get_or_create_id("a"), tournament.get_or_create_id("b"));
+        let win = |tournament: &Tournament| {
+            let conclusion = Conclusion::new(Verdict::RedWon, Reason::Checkmate);
+            let game = Game::opening();
+            let record = Record::new(
+                "a".to_owned(),
+                "b".to_owned(),
+                chrono::Local::now(),
+                Duration::ZERO,
+                &game,
+                Vec::new(),
+                conclusion,
+            );
+            tournament.players[a].write().unwrap().record(b, &record);
+        };
+
+        // a long winning streak from before the test would otherwise decide it right away
+        (0..100).for_each(|_| win(&tournament));
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        tournament.sprt("a", "b", sprt).unwrap();
+
+        let scores = |tournament: &Tournament| {
+            let scores = tournament.iter_tests().map(|(.., score)| score.total());
+            scores.collect::<Vec<_>>()
+        };
+        assert_eq!(scores(&tournament), [0]);
+
+        win(&tournament);
+        tournament.update_tests();
+        assert_eq!(scores(&tournament), [1]);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::arbiter::tournament::PlayerId;
+use chinese_chess::game::Game;
+use std::collections::{HashMap, VecDeque};
+
+// positions games start from, which every pairing cycles through on its own with each opening played as a pair of
+// games with colors reversed, so that neither player gets the better side of an opening more often
+#[derive(Default)]
+pub struct Openings {
+    games: Vec<Game>,                                     // the standard opening is used if empty
+    next: HashMap<(PlayerId, PlayerId), usize>,           // the next new opening of each pairing, in either color
+    owed: HashMap<(PlayerId, PlayerId), VecDeque<usize>>, // openings that still have to be played by (red, black)
+}
+
+impl Openings {
+    pub fn new(games: Vec<Game>) -> Self {
+        Self {
+            games,
+            ..Self::default()
+        }
+    }
+
+    // takes the opening of a game with home as red, which is the oldest one that away played as red and home has not
+    // yet, or otherwise the next new one of the pairing that away then owes a game as red with
+    pub fn start(&mut self, home: PlayerId, away: PlayerId) -> Option<usize> {
+        if self.games.is_empty() {
+            return None;
+        }
+
+        if let Some(opening) = self.owed.get_mut(&(home, away)).and_then(VecDeque::pop_front) {
+            return Some(opening);
+        }
+
+        let next = self.next.entry(pairing(home, away)).or_default();
+        let opening = *next % self.games.len();
+        *next += 1;
+        self.owed.entry((away, home)).or_default().push_back(opening);
+        Some(opening)
+    }
+
+    // gives back the opening of a game that did not take place, so that the next game with the same colors uses it
+    pub fn restore(&mut self, home: PlayerId, away: PlayerId, opening: Option<usize>) {
+        if let Some(opening) = opening {
+            self.owed.entry((home, away)).or_default().push_front(opening);
+        }
+    }
+
+    pub fn game(&self, opening: Option<usize>) -> Game {
+        opening.map_or_else(Game::opening, |opening| self.games[opening].clone())
+    }
+}
+
+fn pairing(home: PlayerId, away: PlayerId) -> (PlayerId, PlayerId) {
+    (home.min(away), home.max(away))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_openings_in_reversed_pairs() {
+        let mut openings = Openings::new(vec![Game::opening(); 3]);
+
+        // pairings cycle independently, and each opening comes back for the other color
+        assert_eq!(openings.start(0, 1), Some(0));
+        assert_eq!(openings.start(0, 1), Some(1));
+        assert_eq!(openings.start(2, 3), Some(0));
+        assert_eq!(openings.start(1, 0), Some(0));
+        assert_eq!(openings.start(1, 0), Some(1));
+        assert_eq!(openings.start(1, 0), Some(2));
+        assert_eq!(openings.start(0, 1), Some(2));
+
+        // a game that did not take place is played again with the same colors
+        let opening = openings.start(0, 1);
+        assert_eq!(opening, Some(0));
+        openings.restore(0, 1, opening);
+        assert_eq!(openings.start(0, 1), Some(0));
+        assert_eq!(openings.start(1, 0), Some(0));
+
+        assert_eq!(Openings::default().start(0, 1), None);
+    }
+}
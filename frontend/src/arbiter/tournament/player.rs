@@ -1,7 +1,11 @@
-use crate::arbiter::tournament::instance::Instance;
+use crate::arbiter::tournament::instance::{Instance, Session};
+use crate::arbiter::tournament::live::Live;
+use crate::arbiter::tournament::record::Record;
+use crate::arbiter::tournament::rules::Rules;
 use crate::arbiter::tournament::status::Status;
 use crate::arbiter::tournament::PlayerId;
-use crate::line_stream::AsyncLineStream;
+use crate::protocol::{Capabilities, Capability};
+use chinese_chess::game::Game;
 use log::{debug, info, trace};
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, RwLock};
@@ -26,9 +30,14 @@ impl Player {
         }
     }
 
-    pub fn create_instance(&mut self, stream: AsyncLineStream) {
-        let name = format!("{}:{}", self.name, self.total_instance_count);
-        let instance = Instance::new(self.id, name, stream);
+    pub fn create_instance(&mut self, session: Arc<Session>, capabilities: Capabilities) {
+        let instance = Instance::new(
+            self.id,
+            self.name.clone(),
+            self.total_instance_count,
+            session,
+            capabilities,
+        );
         self.instances.push_back(instance);
         self.total_instance_count += 1;
     }
@@ -41,10 +50,55 @@ impl Player {
 
         trace!(
             "'{}' enqueued {count} matches against player with id '{away}': {} ",
-            self.name, *status
+            self.name,
+            status
         );
     }
 
+    // disconnects the idle instances, or only the one with the name, returning how many there were
+    pub fn kick(&mut self, instance: Option<&str>) -> usize {
+        let (kicked, kept): (VecDeque<_>, _) = std::mem::take(&mut self.instances)
+            .into_iter()
+            .partition(|current| instance.is_none_or(|name| current.name == name));
+
+        self.instances = kept;
+        kicked.into_iter().map(Instance::kick).count()
+    }
+
+    // removes the idle instances that can be pinged so that they can be checked without being matched meanwhile
+    pub fn take_idle(&mut self) -> Vec<Instance> {
+        let (taken, kept): (VecDeque<_>, _) = std::mem::take(&mut self.instances)
+            .into_iter()
+            .partition(|instance| instance.supports(Capability::Ping));
+
+        self.instances = kept;
+        taken.into()
+    }
+
+    pub fn restore(&mut self, instance: Instance) {
+        self.instances.push_back(instance);
+    }
+
+    pub fn reset_status(&mut self) {
+        info!("reset scores of player '{}'", self.name);
+        self.status.values_mut().for_each(Status::reset);
+    }
+
+    // removes all matches queued against away that have not started yet, returning how many there were
+    pub fn dequeue(&mut self, away: PlayerId) -> u32 {
+        let Some(status) = self.status.get_mut(&away) else {
+            return 0;
+        };
+
+        std::mem::take(&mut status.queued)
+    }
+
+    // record a finished game against away outside of play, such as one loaded from storage
+    pub fn record(&mut self, away: PlayerId, record: &Record) {
+        assert_ne!(self.id, away);
+        self.status.entry(away).or_default().record(record);
+    }
+
     pub fn iter_queued(&self) -> impl Iterator<Item = (PlayerId, u32)> {
         self.status
             .iter()
@@ -52,11 +106,30 @@ impl Player {
             .filter(|(_, queued)| *queued > 0)
     }
 
+    // names of the instances that are connected and not playing right now
+    pub fn iter_idle(&self) -> impl Iterator<Item = &str> {
+        self.instances.iter().map(|instance| instance.name.as_str())
+    }
+
+    // instances that ever connected, including ones that left since
+    pub fn instance_count(&self) -> u32 {
+        self.total_instance_count
+    }
+
+    pub fn status(&self, away: PlayerId) -> Status {
+        self.status.get(&away).cloned().unwrap_or_default()
+    }
+
     pub fn iter_status(&self) -> impl Iterator<Item = (PlayerId, Status)> {
-        self.status.iter().map(|(id, status)| (*id, *status))
+        self.status.iter().map(|(id, status)| (*id, status.clone()))
     }
 
-    pub fn play(home: Arc<RwLock<Self>>, away: Arc<RwLock<Self>>) -> Option<impl Future<Output = ()>> {
+    pub fn play(
+        home: Arc<RwLock<Self>>,
+        away: Arc<RwLock<Self>>,
+        game: Game,
+        rules: Rules,
+    ) -> Option<(Arc<Live>, impl Future<Output = Option<Record>> + use<>)> {
         assert!(!Arc::ptr_eq(&home, &away));
 
         // borrow instance from away
@@ -107,34 +180,51 @@ impl Player {
             home.read().unwrap().name
         );
 
-        Some(async move {
-            let home_name = home_instance.name.to_owned();
-            let away_name = away_instance.name.to_owned();
-            let (outcome, home_instance, away_instance) = Instance::compete(home_instance, away_instance).await;
-
-            // return away instance
-            if let Some(away_instance) = away_instance {
-                let mut away = away.write().unwrap();
-                away.instances.push_back(away_instance);
-            }
+        let live = Arc::new(Live::new(
+            (home_instance.id, home_instance.name.clone()),
+            (away_instance.id, away_instance.name.clone()),
+            &game,
+        ));
+
+        let future = {
+            let live = live.clone();
+            async move {
+                let home_name = home_instance.name.to_owned();
+                let away_name = away_instance.name.to_owned();
+                let (record, home_instance, away_instance) =
+                    Instance::compete(home_instance, away_instance, game, rules, &live).await;
+                live.finish(record.as_ref().map(|record| record.conclusion));
+
+                // return away instance
+                if let Some(away_instance) = away_instance {
+                    let mut away = away.write().unwrap();
+                    away.instances.push_back(away_instance);
+                }
 
-            // update status and return home instance
-            let mut home = home.write().unwrap();
-            let status = home.status.get_mut(&away_id).unwrap();
+                // update status and return home instance
+                let mut home = home.write().unwrap();
+                let status = home.status.get_mut(&away_id).unwrap();
+
+                if let Some(record) = &record {
+                    status.record(record);
+                    status.running -= 1;
+                } else if live.aborted() {
+                    status.running -= 1;
+                } else {
+                    status.queued += 1;
+                    status.running -= 1;
+                }
 
-            if let Some(outcome) = outcome {
-                status.score.merge(&outcome.into());
-                status.running -= 1;
-            } else {
-                status.queued += 1;
-                status.running -= 1;
-            }
+                debug!("match between '{}' and '{}' done: {}", home_name, away_name, status);
 
-            debug!("match between '{}' and '{}' done: {}", home_name, away_name, status);
+                if let Some(home_instance) = home_instance {
+                    home.instances.push_back(home_instance)
+                }
 
-            if let Some(home_instance) = home_instance {
-                home.instances.push_back(home_instance)
+                record
             }
-        })
+        };
+
+        Some((live, future))
     }
 }
@@ -0,0 +1,265 @@
+use crate::protocol::{Analysis, Conclusion, Protocol, Verdict};
+use chinese_chess::board::Board;
+use chinese_chess::game::Game;
+use chinese_chess::location::Move;
+use chinese_chess::pgn::Pgn;
+use chrono::{DateTime, Local};
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// everything about a finished game, stored as one line when persisted
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub red: String,
+    pub black: String,
+    pub start: DateTime<Local>,
+    pub duration: Duration,
+    pub fen: String,
+    pub red_turn: bool,
+    pub moves: Vec<Move>,
+    pub times: Vec<u32>,                 // milliseconds spent on each move
+    pub analyses: Vec<Option<Analysis>>, // the final search of each move, if the player reported one
+    pub conclusion: Conclusion,
+}
+
+impl Record {
+    pub fn new(
+        red: String,
+        black: String,
+        start: DateTime<Local>,
+        duration: Duration,
+        game: &Game,
+        times: Vec<u32>,
+        conclusion: Conclusion,
+    ) -> Self {
+        let moves = game.history().iter().map(|&(mv, _)| mv).collect::<Vec<_>>();
+        assert_eq!(moves.len(), times.len());
+
+        let mut initial = game.clone();
+        moves.iter().for_each(|_| {
+            initial.undo_move();
+        });
+
+        Self {
+            red,
+            black,
+            start,
+            duration,
+            fen: initial.board().fen(),
+            red_turn: initial.red_turn(),
+            analyses: vec![None; moves.len()],
+            moves,
+            times,
+            conclusion,
+        }
+    }
+
+    pub fn with_analyses(mut self, analyses: Vec<Option<Analysis>>) -> Self {
+        assert_eq!(analyses.len(), self.moves.len());
+        self.analyses = analyses;
+        self
+    }
+
+    // the initial position, which identifies the opening the game was played from
+    pub fn opening(&self) -> String {
+        format!("{} {}", self.fen, if self.red_turn { "w" } else { "b" })
+    }
+
+    // replays the moves from the initial position, none if the record does not describe a legal game
+    pub fn game(&self) -> Option<Game> {
+        let mut game = Game::new(Board::from_fen(&self.fen)?, self.red_turn);
+        for &mv in &self.moves {
+            if !game.can_move(mv) {
+                return None;
+            }
+            game.make_move(mv);
+        }
+        Some(game)
+    }
+
+    pub fn append(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        writeln!(file, "{self}")
+    }
+
+    pub fn pgn(&self) -> Option<Pgn> {
+        let mut pgn = Pgn::new(self.game()?);
+        let result = match self.conclusion.verdict {
+            Verdict::RedWon => "1-0",
+            Verdict::BlackWon => "0-1",
+            Verdict::Draw => "1/2-1/2",
+        };
+
+        pgn.set_header("Event", "arbiter");
+        pgn.set_header("Date", self.start.format("%Y.%m.%d").to_string());
+        pgn.set_header("Red", &self.red);
+        pgn.set_header("Black", &self.black);
+        pgn.set_header("Result", result);
+        pgn.set_header("Time", self.start.format("%T").to_string());
+        pgn.set_header("Termination", self.conclusion.reason.to_string());
+
+        for (ply, analysis) in self.analyses.iter().enumerate() {
+            if let Some(analysis) = analysis {
+                pgn.set_comment(ply, &analysis.to_string());
+            }
+        }
+        Some(pgn)
+    }
+
+    pub fn append_pgn(&self, path: &Path) -> std::io::Result<()> {
+        let pgn = self
+            .pgn()
+            .ok_or_else(|| std::io::Error::other("record does not describe a legal game"))?;
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        writeln!(file, "{pgn}")
+    }
+
+    // reads all records in a file that does not need to exist, also returning the number of invalid lines
+    pub fn load(path: &Path) -> std::io::Result<(Vec<Record>, usize)> {
+        if !path.exists() {
+            return Ok((Vec::new(), 0));
+        }
+
+        let mut records = Vec::new();
+        let mut invalid = 0;
+
+        for line in BufReader::new(std::fs::File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match line.parse() {
+                Ok(record) => records.push(record),
+                Err(()) => invalid += 1,
+            }
+        }
+
+        Ok((records, invalid))
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {}",
+            self.start.to_rfc3339(),
+            self.red,
+            self.black,
+            self.fen,
+            self.red_turn,
+            self.conclusion.verdict,
+            self.conclusion.reason,
+            self.duration.as_millis(),
+        )?;
+
+        // analyses are written like info messages but with commas, so that each move stays a single part
+        for ((mv, time), analysis) in self.moves.iter().zip(&self.times).zip(&self.analyses) {
+            write!(f, " {mv}:{time}")?;
+            if let Some(analysis) = analysis {
+                write!(f, ":{}", analysis.to_string().replace(' ', ","))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Record {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let mut next = || parts.next().ok_or(());
+
+        let start = DateTime::parse_from_rfc3339(next()?).map_err(|_| ())?;
+        let red = next()?.to_string();
+        let black = next()?.to_string();
+        let fen = next()?.to_string();
+        let red_turn = next()?.parse().map_err(|_| ())?;
+        let verdict = next()?.parse()?;
+        let reason = next()?.parse()?;
+        let duration = Duration::from_millis(next()?.parse().map_err(|_| ())?);
+
+        let mut moves = Vec::new();
+        let mut times = Vec::new();
+        let mut analyses = Vec::new();
+
+        for part in parts {
+            let mut fields = part.splitn(3, ':');
+            moves.push(fields.next().ok_or(())?.parse().map_err(|_| ())?);
+            times.push(fields.next().ok_or(())?.parse().map_err(|_| ())?);
+            analyses.push(match fields.next() {
+                Some(analysis) => Some(Protocol::decode_analysis(analysis.split(',')).map_err(|_| ())?),
+                None => None,
+            });
+        }
+
+        Ok(Self {
+            red,
+            black,
+            start: start.with_timezone(&Local),
+            duration,
+            fen,
+            red_turn,
+            moves,
+            times,
+            analyses,
+            conclusion: Conclusion::new(verdict, reason),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Reason, Verdict};
+
+    #[test]
+    fn round_trip() {
+        let mut game = Game::opening();
+        for mv in ["h2e2", "h9g7", "h0g2"] {
+            game.make_move(mv.parse().unwrap());
+        }
+
+        let conclusion = Conclusion::new(Verdict::BlackWon, Reason::Timeout);
+        let duration = Duration::from_millis(6543);
+        let record = Record::new(
+            "a".into(),
+            "b".into(),
+            Local::now(),
+            duration,
+            &game,
+            vec![12, 345, 6789],
+            conclusion,
+        );
+        assert_eq!(record.fen, Game::opening().board().fen());
+
+        let analysis = Protocol::decode_analysis("depth 4 score -15 nodes 900 pv h0g2 i9h9".split(' ')).unwrap();
+        let record = record.with_analyses(vec![None, None, Some(analysis.clone())]);
+        assert!(record
+            .to_string()
+            .ends_with(" h0g2:6789:depth,4,score,-15,nodes,900,pv,h0g2,i9h9"));
+
+        let parsed = record.to_string().parse::<Record>().unwrap();
+        assert_eq!(parsed.to_string(), record.to_string());
+        assert_eq!(parsed.duration, duration);
+        assert_eq!(parsed.conclusion, conclusion);
+        assert_eq!(parsed.game().unwrap().board().fen(), game.board().fen());
+        assert_eq!(parsed.analyses, [None, None, Some(analysis)]);
+
+        // records from before analyses were kept are still read
+        let old = record.to_string().rsplit_once(':').unwrap().0.to_owned();
+        assert_eq!(old.parse::<Record>().unwrap().analyses, [None, None, None]);
+
+        let pgn = record.pgn().unwrap().to_string().parse::<Pgn>().unwrap();
+        assert_eq!(pgn.header("Result"), Some("0-1"));
+        assert_eq!(pgn.header("Termination"), Some("timeout"));
+        assert_eq!(pgn.game().board().fen(), game.board().fen());
+        assert_eq!(pgn.comment(2), Some("depth 4 score -15 nodes 900 pv h0g2 i9h9"));
+    }
+}
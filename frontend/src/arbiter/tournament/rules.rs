@@ -0,0 +1,79 @@
+use crate::arbiter::tournament::adjudication::Adjudication;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Rules {
+    pub move_time: u32,          // milliseconds each player gets for every move
+    pub grace_time: Option<u32>, // milliseconds past the move time before players that support stop forfeit, if ever
+    pub illegal_move: IllegalMovePolicy,
+    pub adjudication: Adjudication,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum IllegalMovePolicy {
+    Forfeit,              // forfeit on the first illegal move
+    Retry { limit: u32 }, // silently prompt again, forfeit after too many illegal moves in one game
+    Warn { limit: u32 },  // same as retry, but tell the player which move was rejected
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Self {
+            move_time: 2000,
+            grace_time: None,
+            illegal_move: IllegalMovePolicy::Warn { limit: 3 },
+            adjudication: Adjudication::default(),
+        }
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IllegalMovePolicy {
+    // whether the player may try again after having played this many illegal moves in the game
+    pub fn allows(&self, count: u32) -> bool {
+        match *self {
+            IllegalMovePolicy::Forfeit => false,
+            IllegalMovePolicy::Retry { limit } | IllegalMovePolicy::Warn { limit } => count <= limit,
+        }
+    }
+
+    pub fn warns(&self) -> bool {
+        matches!(self, IllegalMovePolicy::Warn { .. })
+    }
+}
+
+impl Display for IllegalMovePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IllegalMovePolicy::Forfeit => write!(f, "forfeit"),
+            IllegalMovePolicy::Retry { limit } => write!(f, "retry:{limit}"),
+            IllegalMovePolicy::Warn { limit } => write!(f, "warn:{limit}"),
+        }
+    }
+}
+
+impl FromStr for IllegalMovePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || format!("unknown illegal move policy '{s}', expected forfeit, retry:<n> or warn:<n>");
+        if s == "forfeit" {
+            return Ok(IllegalMovePolicy::Forfeit);
+        }
+
+        let (kind, limit) = s.split_once(':').ok_or_else(unknown)?;
+        let limit = limit.parse().map_err(|_| unknown())?;
+
+        match kind {
+            "retry" => Ok(IllegalMovePolicy::Retry { limit }),
+            "warn" => Ok(IllegalMovePolicy::Warn { limit }),
+            _ => Err(unknown()),
+        }
+    }
+}
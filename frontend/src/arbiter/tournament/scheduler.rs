@@ -0,0 +1,117 @@
+use crate::arbiter::tournament::PlayerId;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// how many games may run at once, in total and per player, unlimited if none
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Limits {
+    pub games: Option<u32>,
+    pub player_games: Option<u32>,
+}
+
+// decides which queued match starts next, taking turns between pairings so that none of them is starved and
+// preferring pairings with players of higher priority
+#[derive(Default, Debug)]
+pub struct Scheduler {
+    pub limits: Limits,
+    priorities: HashMap<PlayerId, i32>,
+    last_started: HashMap<(PlayerId, PlayerId), u64>, // when each pairing last started a game, in either color
+    started: u64,
+}
+
+impl Scheduler {
+    pub fn priority(&self, player: PlayerId) -> i32 {
+        self.priorities.get(&player).copied().unwrap_or(0)
+    }
+
+    pub fn set_priority(&mut self, player: PlayerId, priority: i32) {
+        self.priorities.insert(player, priority);
+    }
+
+    // sorts the (home, away, queued) candidates from the one that should start first, which is the pairing with the
+    // highest priority player that waited the longest since its last game
+    pub fn order(&self, candidates: &mut [(PlayerId, PlayerId, u32)]) {
+        candidates.sort_by_key(|&(home, away, queued)| {
+            let priority = self.priority(home).max(self.priority(away));
+            let last_started = self.last_started.get(&pairing(home, away)).copied();
+            (Reverse(priority), last_started, Reverse(queued))
+        });
+    }
+
+    // whether another game between the two players fits within the limits given the (red, black) games running
+    pub fn allows(&self, running: &[(PlayerId, PlayerId)], home: PlayerId, away: PlayerId) -> bool {
+        if self.limits.games.is_some_and(|limit| running.len() as u32 >= limit) {
+            return false;
+        }
+
+        let playing = |player| {
+            running
+                .iter()
+                .filter(|&&(red, black)| red == player || black == player)
+                .count() as u32
+        };
+        let limit = self.limits.player_games.unwrap_or(u32::MAX);
+        playing(home) < limit && playing(away) < limit
+    }
+
+    pub fn start(&mut self, home: PlayerId, away: PlayerId) {
+        self.started += 1;
+        self.last_started.insert(pairing(home, away), self.started);
+    }
+}
+
+fn pairing(home: PlayerId, away: PlayerId) -> (PlayerId, PlayerId) {
+    (home.min(away), home.max(away))
+}
+
+impl Display for Limits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let limit = |limit: Option<u32>| limit.map_or("unlimited".to_owned(), |limit| limit.to_string());
+        write!(
+            f,
+            "{} games in total and {} per player",
+            limit(self.games),
+            limit(self.player_games)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_turns_between_pairings() {
+        let mut scheduler = Scheduler::default();
+        let mut candidates = [(0, 1, 10), (2, 3, 1), (1, 0, 5)];
+
+        scheduler.order(&mut candidates);
+        assert_eq!(candidates[0], (0, 1, 10));
+
+        // the pairing that just started goes last, in both colors
+        scheduler.start(0, 1);
+        scheduler.order(&mut candidates);
+        assert_eq!(candidates, [(2, 3, 1), (0, 1, 10), (1, 0, 5)]);
+
+        scheduler.start(2, 3);
+        scheduler.set_priority(3, 1);
+        scheduler.order(&mut candidates);
+        assert_eq!(candidates[0], (2, 3, 1));
+    }
+
+    #[test]
+    fn respects_limits() {
+        let mut scheduler = Scheduler::default();
+        let running = [(0, 1), (0, 2)];
+        assert!(scheduler.allows(&running, 0, 3));
+
+        scheduler.limits.player_games = Some(2);
+        assert!(!scheduler.allows(&running, 0, 3));
+        assert!(!scheduler.allows(&running, 3, 0));
+        assert!(scheduler.allows(&running, 1, 2));
+
+        scheduler.limits.games = Some(2);
+        assert!(!scheduler.allows(&running, 1, 2));
+    }
+}
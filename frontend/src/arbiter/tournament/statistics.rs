@@ -0,0 +1,271 @@
+use crate::arbiter::tournament::status::Score;
+use std::fmt::Display;
+
+// elo difference with the half width of its 95% confidence interval
+#[derive(Copy, Clone, Debug)]
+pub struct Estimate {
+    pub elo: f64,
+    pub error: f64,
+}
+
+// sequential probability ratio test of whether a player is at least elo1 rather than at most elo0 stronger, with the
+// chances of wrongly accepting and wrongly rejecting elo1 bounded by alpha and beta
+#[derive(Copy, Clone, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Decision {
+    Accept, // the player is at least elo1 stronger
+    Reject, // the player is at most elo0 stronger
+}
+
+const Z_95: f64 = 1.959964;
+
+// elo difference that makes the expected score per game equal to this
+pub fn elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+// estimate from individual games, treating every game as an independent sample
+pub fn trinomial(score: &Score) -> Option<Estimate> {
+    let samples = [(score.loss, 0.0), (score.draw, 0.5), (score.win, 1.0)];
+    estimate(&samples)
+}
+
+// estimate from pairs of games with the same opening and colors reversed, which cancels out most of the opening bias
+pub fn pentanomial(pairs: &[u32; 5]) -> Option<Estimate> {
+    let samples: [(u32, f64); 5] = std::array::from_fn(|index| (pairs[index], index as f64 / 4.0));
+    estimate(&samples)
+}
+
+// likelihood of superiority, the probability that the player is stronger given the decisive games
+pub fn los(score: &Score) -> f64 {
+    let decisive = (score.win + score.loss) as f64;
+    if decisive == 0.0 {
+        return 0.5;
+    }
+
+    0.5 * (1.0 + erf((score.win as f64 - score.loss as f64) / (2.0 * decisive).sqrt()))
+}
+
+// maximum likelihood ratings of all players from their scores as red against each other, with each player drawing one
+// virtual game against an average opponent so that perfect scores still have a finite rating
+pub fn ratings(count: usize, results: &[(usize, usize, Score)]) -> Vec<f64> {
+    const ITERATIONS: usize = 1000;
+
+    let mut games = vec![vec![0.0; count]; count];
+    let mut points = vec![0.5; count];
+
+    for &(red, black, score) in results {
+        let total = score.total() as f64;
+        games[red][black] += total;
+        games[black][red] += total;
+        points[red] += score.win as f64 + score.draw as f64 / 2.0;
+        points[black] += score.loss as f64 + score.draw as f64 / 2.0;
+    }
+
+    // minorization-maximization iterations for the bradley-terry model
+    let mut strengths = vec![1.0; count];
+    for _ in 0..ITERATIONS {
+        let next = (0..count)
+            .map(|player| {
+                let strength = strengths[player];
+                let opponents = (0..count)
+                    .map(|other| games[player][other] / (strength + strengths[other]))
+                    .sum::<f64>();
+                points[player] / (opponents + 1.0 / (strength + 1.0))
+            })
+            .collect::<Vec<_>>();
+        strengths = next;
+    }
+
+    let ratings = strengths
+        .iter()
+        .map(|strength| 400.0 * strength.log10())
+        .collect::<Vec<_>>();
+    let mean = ratings.iter().sum::<f64>() / count.max(1) as f64;
+    ratings.into_iter().map(|rating| rating - mean).collect()
+}
+
+fn estimate(samples: &[(u32, f64)]) -> Option<Estimate> {
+    let (count, mean, variance) = moments(samples)?;
+    if mean <= 0.0 || mean >= 1.0 {
+        return None;
+    }
+
+    let deviation = (variance / count).sqrt() * Z_95;
+    let lower = elo((mean - deviation).max(f64::EPSILON));
+    let upper = elo((mean + deviation).min(1.0 - f64::EPSILON));
+
+    Some(Estimate {
+        elo: elo(mean),
+        error: (upper - lower) / 2.0,
+    })
+}
+
+// number of samples with their mean and variance
+fn moments(samples: &[(u32, f64)]) -> Option<(f64, f64, f64)> {
+    let count = samples.iter().map(|&(count, _)| count as f64).sum::<f64>();
+    if count == 0.0 {
+        return None;
+    }
+
+    let mean = samples.iter().map(|&(count, value)| count as f64 * value).sum::<f64>() / count;
+    let variance = samples
+        .iter()
+        .map(|&(count, value)| count as f64 * (value - mean).powi(2))
+        .sum::<f64>()
+        / count;
+
+    Some((count, mean, variance))
+}
+
+// expected score per game of a player this much elo stronger
+fn expected(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+impl Sprt {
+    // log-likelihood ratio of elo1 against elo0 given the score, using the normal approximation of the trinomial model
+    pub fn llr(&self, score: &Score) -> f64 {
+        let samples = [(score.loss, 0.0), (score.draw, 0.5), (score.win, 1.0)];
+        let Some((count, mean, _)) = moments(&samples) else {
+            return 0.0;
+        };
+
+        // the variance includes one virtual win and loss so that the first few games, which often all end the same
+        // way, cannot decide the test by themselves
+        let samples = [(score.loss + 1, 0.0), (score.draw, 0.5), (score.win + 1, 1.0)];
+        let (_, _, variance) = moments(&samples).unwrap();
+
+        let (score0, score1) = (expected(self.elo0), expected(self.elo1));
+        count * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    // the llr bounds below which elo0 is accepted and above which elo1 is accepted
+    pub fn bounds(&self) -> (f64, f64) {
+        let lower = (self.beta / (1.0 - self.alpha)).ln();
+        let upper = ((1.0 - self.beta) / self.alpha).ln();
+        (lower, upper)
+    }
+
+    pub fn decide(&self, score: &Score) -> Option<Decision> {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+
+        if llr >= upper {
+            Some(Decision::Accept)
+        } else if llr <= lower {
+            Some(Decision::Reject)
+        } else {
+            None
+        }
+    }
+}
+
+// abramowitz and stegun approximation 7.1.26, accurate to about 1e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let result = 1.0 - polynomial * (-x * x).exp();
+    if x < 0.0 {
+        -result
+    } else {
+        result
+    }
+}
+
+impl Display for Sprt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "elo0 {} elo1 {} alpha {} beta {}",
+            self.elo0, self.elo1, self.alpha, self.beta
+        )
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Decision::Accept => write!(f, "accepted elo1"),
+            Decision::Reject => write!(f, "accepted elo0"),
+        }
+    }
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:+.1} ± {:.1}", self.elo, self.error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(win: u32, loss: u32, draw: u32) -> Score {
+        Score { win, loss, draw }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 0.1, "{actual} is not close to {expected}");
+    }
+
+    #[test]
+    fn trinomial_estimate() {
+        let estimate = trinomial(&score(27, 23, 0)).unwrap();
+        assert_close(estimate.elo, 27.9);
+        assert_close(estimate.error, 99.3);
+
+        assert_close(trinomial(&score(10, 10, 30)).unwrap().elo, 0.0);
+        assert!(trinomial(&score(5, 0, 0)).is_none());
+        assert!(trinomial(&score(0, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn pentanomial_estimate() {
+        let estimate = pentanomial(&[1, 2, 10, 5, 2]).unwrap();
+        assert_close(estimate.elo, 43.7);
+        assert_close(estimate.error, 74.0);
+    }
+
+    #[test]
+    fn likelihood_of_superiority() {
+        assert_close(los(&score(0, 0, 10)), 0.5);
+        assert_close(los(&score(27, 23, 0)) * 100.0, 71.4);
+        assert!(los(&score(10, 30, 0)) < 0.01);
+    }
+
+    #[test]
+    fn sprt_decisions() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+
+        assert_close(sprt.bounds().1, 2.944);
+        assert_close(sprt.llr(&score(120, 40, 40)), 3.437);
+        assert_eq!(sprt.decide(&score(120, 40, 40)), Some(Decision::Accept));
+        assert_eq!(sprt.decide(&score(40, 120, 40)), Some(Decision::Reject));
+        assert_eq!(sprt.decide(&score(50, 50, 0)), None);
+        assert_eq!(sprt.decide(&score(0, 0, 0)), None);
+        assert_eq!(sprt.decide(&score(1, 0, 0)), None);
+        assert_eq!(sprt.decide(&score(0, 0, 400)), Some(Decision::Reject));
+    }
+
+    #[test]
+    fn ratings_order_players() {
+        let results = [(0, 1, score(8, 2, 0)), (1, 2, score(8, 2, 0)), (2, 0, score(1, 9, 0))];
+        let ratings = ratings(3, &results);
+        assert!(ratings[0] > ratings[1] && ratings[1] > ratings[2]);
+        assert_close(ratings.iter().sum::<f64>(), 0.0);
+    }
+}
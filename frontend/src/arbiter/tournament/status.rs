@@ -1,11 +1,15 @@
-use chinese_chess::game::Outcome;
+use crate::arbiter::tournament::record::Record;
+use crate::protocol::{Reason, Verdict};
 use std::fmt::Display;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Status {
     pub score: Score,
+    pub reasons: [Score; Reason::ALL.len()], // score broken down by how the games ended
     pub queued: u32,
     pub running: u32,
+    red: Vec<(String, u8)>,   // opening and half points of each game played as red, in order
+    black: Vec<(String, u8)>, // opening and half points of each game played as black, in order
 }
 
 #[derive(Copy, Clone)]
@@ -19,15 +23,71 @@ impl Status {
     pub fn new() -> Self {
         Self {
             score: Score::new(),
+            reasons: [Score::new(); Reason::ALL.len()],
             queued: 0,
             running: 0,
+            red: Vec::new(),
+            black: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, record: &Record) {
+        let conclusion = record.conclusion;
+        let score: Score = conclusion.verdict.into();
+        self.score.merge(&score);
+        self.reasons[conclusion.reason as usize].merge(&score);
+        self.red.push((record.opening(), (score.win * 2 + score.draw) as u8));
+    }
+
+    // counts of game pairs by their total half points from 0 to 4, pairing each game as red with the earliest unpaired
+    // game as black from the same opening, games without such a counterpart are left out
+    pub fn pentanomial(&self) -> [u32; 5] {
+        let mut pairs = [0; 5];
+        let mut unpaired = self.black.iter().collect::<Vec<_>>();
+        for (opening, red) in &self.red {
+            if let Some(index) = unpaired.iter().position(|(other, _)| other == opening) {
+                let (_, black) = unpaired.remove(index);
+                pairs[(red + black) as usize] += 1;
+            }
         }
+        pairs
+    }
+
+    // forgets every finished game while keeping queued and running ones
+    pub fn reset(&mut self) {
+        self.score = Score::new();
+        self.reasons = [Score::new(); Reason::ALL.len()];
+        self.red.clear();
+        self.black.clear();
+    }
+
+    pub fn iter_reasons(&self) -> impl Iterator<Item = (Reason, Score)> {
+        Reason::ALL
+            .into_iter()
+            .zip(self.reasons)
+            .filter(|(_, score)| score.total() > 0)
+    }
+
+    pub fn negate(&mut self) {
+        self.score.negate();
+        self.reasons.iter_mut().for_each(Score::negate);
+
+        std::mem::swap(&mut self.red, &mut self.black);
+        self.red
+            .iter_mut()
+            .chain(&mut self.black)
+            .for_each(|(_, points)| *points = 2 - *points);
     }
 
     pub fn merge(&mut self, status: &Self) {
         self.score.merge(&status.score);
+        for (reason, score) in self.reasons.iter_mut().zip(&status.reasons) {
+            reason.merge(score);
+        }
         self.queued += status.queued;
         self.running += status.running;
+        self.red.extend(status.red.iter().cloned());
+        self.black.extend(status.black.iter().cloned());
     }
 }
 
@@ -61,14 +121,27 @@ impl Score {
     pub fn negate(&mut self) {
         (self.win, self.loss) = (self.loss, self.win);
     }
+
+    pub fn total(&self) -> u32 {
+        self.win + self.loss + self.draw
+    }
+
+    // games scored after an earlier score of the same players was taken
+    pub fn since(&self, start: &Self) -> Self {
+        Self {
+            win: self.win.saturating_sub(start.win),
+            loss: self.loss.saturating_sub(start.loss),
+            draw: self.draw.saturating_sub(start.draw),
+        }
+    }
 }
 
-impl From<Outcome> for Score {
-    fn from(outcome: Outcome) -> Self {
-        match outcome {
-            Outcome::RedWon => Self { win: 1, ..Self::new() },
-            Outcome::BlackWon => Self { loss: 1, ..Self::new() },
-            Outcome::Stalemate | Outcome::MoveRule => Self { draw: 1, ..Self::new() },
+impl From<Verdict> for Score {
+    fn from(verdict: Verdict) -> Self {
+        match verdict {
+            Verdict::RedWon => Self { win: 1, ..Self::new() },
+            Verdict::BlackWon => Self { loss: 1, ..Self::new() },
+            Verdict::Draw => Self { draw: 1, ..Self::new() },
         }
     }
 }
@@ -84,3 +157,49 @@ impl Display for Score {
         write!(f, "w({}) l({}) d({})", self.win, self.loss, self.draw)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Conclusion;
+    use chinese_chess::game::Game;
+    use chrono::Local;
+    use std::time::Duration;
+
+    fn record(fen: &str, verdict: Verdict) -> Record {
+        let mut record = Record::new(
+            "a".to_owned(),
+            "b".to_owned(),
+            Local::now(),
+            Duration::ZERO,
+            &Game::opening(),
+            Vec::new(),
+            Conclusion::new(verdict, Reason::Checkmate),
+        );
+        record.fen = fen.to_owned();
+        record
+    }
+
+    #[test]
+    fn pentanomial_pairs_openings() {
+        // games of a from the perspective of a, with the colors reversed game by game through negate
+        let mut red = Status::new();
+        red.record(&record("first", Verdict::RedWon));
+        red.record(&record("second", Verdict::Draw));
+        red.record(&record("third", Verdict::RedWon));
+
+        // b played the second opening as red before the first, and never played the third as red
+        let mut black = Status::new();
+        black.record(&record("second", Verdict::BlackWon));
+        black.record(&record("first", Verdict::RedWon));
+        black.negate();
+
+        let mut status = red;
+        status.merge(&black);
+        assert_eq!(status.score.total(), 5);
+        assert_eq!(status.score.win, 3);
+
+        // first: win as red and loss as black, second: draw as red and win as black
+        assert_eq!(status.pentanomial(), [0, 0, 1, 1, 0]);
+    }
+}
@@ -0,0 +1,459 @@
+use chinese_chess::board::Board;
+use chinese_chess::game::Game;
+use chinese_chess::location::Move;
+use chinese_chess::notation;
+use clap::Parser;
+use frontend::line_stream::LineStream;
+use frontend::protocol::{Analysis, ArbiterMessage, Capabilities, Capability, PROTOCOL_VERSION, PlayerMessage};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+// connects an external ucci or uci engine to the arbiter as a normal player
+#[derive(Parser, Debug)]
+struct Arguments {
+    #[arg(short, long, default_value = "127.0.0.1")]
+    ip: IpAddr,
+
+    #[arg(short, long, default_value_t = 6000)]
+    port: u16,
+
+    #[arg(short, long, help = "Key to authenticate with if the arbiter requires one")]
+    key: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Name to register with, the name reported by the engine if unspecified"
+    )]
+    name: Option<String>,
+
+    #[arg(help = "Engine executable to launch")]
+    engine: PathBuf,
+
+    #[arg(last = true, help = "Arguments passed to the engine")]
+    engine_args: Vec<String>,
+
+    #[arg(long, help = "Speak uci instead of ucci to the engine")]
+    uci: bool,
+
+    #[arg(
+        short,
+        long = "option",
+        help = "Engine option to set before playing, as <name>=<value>"
+    )]
+    options: Vec<String>,
+
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "Milliseconds of the move time kept back for communication"
+    )]
+    margin: u32,
+}
+
+struct Engine {
+    child: Child,
+    input: ChildStdin,
+    output: Receiver<String>,
+    uci: bool,
+    name: Option<String>,
+}
+
+impl Engine {
+    const STARTUP_TIME: Duration = Duration::from_secs(10);
+    const MATE_SCORE: i32 = 1_000_000;
+
+    fn spawn(arguments: &Arguments) -> Result<Self, std::io::Error> {
+        let mut child = Command::new(&arguments.engine)
+            .args(&arguments.engine_args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let input = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // read on a separate thread so the arbiter can be answered while the engine is thinking
+        let (sender, output) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Self {
+            child,
+            input,
+            output,
+            uci: arguments.uci,
+            name: None,
+        };
+
+        engine.send(if engine.uci { "uci" } else { "ucci" })?;
+        for line in engine.wait_for(if engine.uci { "uciok" } else { "ucciok" })? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = Some(name.split_whitespace().collect::<Vec<_>>().join("_"));
+            }
+        }
+
+        for option in &arguments.options {
+            let (name, value) = option.split_once('=').unwrap_or((option, ""));
+            if engine.uci {
+                engine.send(&format!("setoption name {name} value {value}"))?;
+            } else {
+                engine.send(&format!("setoption {name} {value}"))?;
+            }
+        }
+
+        engine.synchronize()?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, line: &str) -> Result<(), std::io::Error> {
+        println!("> {line}");
+        writeln!(self.input, "{line}")?;
+        self.input.flush()
+    }
+
+    // blocks until the engine prints a line starting with the token, returning every line up to and including it
+    fn wait_for(&self, token: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.receive(Self::STARTUP_TIME)?.ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("engine did not reply with {token}"),
+                )
+            })?;
+
+            let done = line.split_whitespace().next() == Some(token);
+            lines.push(line);
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+
+    fn receive(&self, timeout: Duration) -> Result<Option<String>, std::io::Error> {
+        match self.output.recv_timeout(timeout) {
+            Ok(line) => {
+                println!("< {line}");
+                Ok(Some(line))
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "engine exited"))
+            }
+        }
+    }
+
+    fn synchronize(&mut self) -> Result<(), std::io::Error> {
+        self.send("isready")?;
+        self.wait_for("readyok").map(|_| ())
+    }
+
+    fn go(&mut self, start: &Game, moves: &[Move], time: u32) -> Result<(), std::io::Error> {
+        let [position, go] = Self::go_commands(self.uci, start, moves, time);
+        self.send(&position)?;
+        self.send(&go)
+    }
+
+    // the position and go commands that start a search of the position after the moves with the time to spend
+    fn go_commands(uci: bool, start: &Game, moves: &[Move], time: u32) -> [String; 2] {
+        let mut position = format!("position fen {}", notation::fen(start));
+        if !moves.is_empty() {
+            let moves = moves.iter().map(|mv| mv.to_string()).collect::<Vec<_>>();
+            position = format!("{position} moves {}", moves.join(" "));
+        }
+
+        let go = if uci {
+            format!("go movetime {time}")
+        } else {
+            format!("go time {time} movestogo 1")
+        };
+        [position, go]
+    }
+
+    // info depth <d> score [cp|mate] <s> nodes <n> nps <n> ... pv <move>...
+    fn parse_info(line: &str) -> Option<Analysis> {
+        let mut tokens = line.split_whitespace().skip(1);
+        let mut depth = None;
+        let mut score = None;
+        let mut nodes = None;
+        let mut nps = None;
+        let mut pv = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "depth" => depth = tokens.next()?.parse().ok(),
+                "score" => {
+                    score = match tokens.next()? {
+                        "cp" => tokens.next()?.parse().ok(),
+                        "mate" => {
+                            let moves = tokens.next()?.parse::<i32>().ok()?;
+                            Some(moves.signum() * Self::MATE_SCORE - moves)
+                        }
+                        score => score.parse().ok(),
+                    }
+                }
+                "nodes" => nodes = tokens.next()?.parse().ok(),
+                "nps" => nps = tokens.next()?.parse().ok(),
+                "pv" => {
+                    pv = tokens.by_ref().map_while(|mv| notation::parse_iccs(mv).ok()).collect();
+                }
+                _ => {}
+            }
+        }
+
+        Some(Analysis {
+            depth: depth?,
+            score: score?,
+            nodes,
+            nps,
+            pv,
+        })
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+fn think(
+    engine: &mut Engine,
+    stream: &LineStream,
+    start: &Game,
+    moves: &[Move],
+    time: u32,
+    capabilities: Capabilities,
+    pending: &mut VecDeque<ArbiterMessage>,
+) -> Result<(), Box<dyn Error>> {
+    engine.go(start, moves, time)?;
+
+    // the engine always ends a search with bestmove, which is only forwarded if the game is still going
+    let mut concluded = false;
+
+    loop {
+        if let Some(line) = engine.receive(Duration::from_millis(10))? {
+            match line.split_whitespace().next() {
+                Some("info") if capabilities.contains(Capability::Info) => {
+                    if let Some(analysis) = Engine::parse_info(&line) {
+                        stream.write(&PlayerMessage::Analysis { analysis })?;
+                    }
+                }
+                Some("bestmove") => {
+                    let mv = line
+                        .split_whitespace()
+                        .nth(1)
+                        .and_then(|mv| notation::parse_iccs(mv).ok());
+                    match mv {
+                        Some(mv) if !concluded => stream.write(&PlayerMessage::Play { mv })?,
+                        Some(_) => {}
+                        None => println!("engine found no move"),
+                    }
+                    return Ok(());
+                }
+                Some("nobestmove") => {
+                    println!("engine found no move");
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        while !concluded && stream.poll() {
+            match stream.read_lenient()? {
+                ArbiterMessage::Stop => engine.send("stop")?,
+                ArbiterMessage::Ping { token } => stream.write(&PlayerMessage::Pong { token })?,
+                message @ ArbiterMessage::Result { .. } => {
+                    engine.send("stop")?;
+                    pending.push_back(message);
+                    concluded = true;
+                }
+                message => pending.push_back(message),
+            }
+        }
+    }
+}
+
+// the token is kept across connections so that a reconnection resumes the previous session, returns without error
+// only once the arbiter rejects the connection
+fn session(
+    engine: &mut Engine,
+    stream: &LineStream,
+    arguments: &Arguments,
+    name: &str,
+    token: &mut Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    let capabilities = Capabilities::from_iter([
+        Capability::Result,
+        Capability::Info,
+        Capability::Ping,
+        Capability::Stop,
+        Capability::Illegal,
+        Capability::Resume,
+        Capability::Error,
+    ]);
+    stream.write(&PlayerMessage::Init {
+        version: PROTOCOL_VERSION,
+        capabilities,
+    })?;
+
+    if let Some(key) = arguments.key.clone() {
+        stream.write(&PlayerMessage::Auth { key })?;
+    }
+
+    let name = name.to_owned();
+    match token.clone() {
+        Some(token) => stream.write(&PlayerMessage::Resume { name, token })?,
+        None => stream.write(&PlayerMessage::Info { name })?,
+    }
+
+    let mut capabilities = Capabilities::none();
+    let mut start = Game::opening();
+    let mut moves = Vec::new();
+    let mut pending = VecDeque::new();
+
+    loop {
+        let message = match pending.pop_front() {
+            Some(message) => message,
+            None => stream.read_lenient()?,
+        };
+
+        match message {
+            ArbiterMessage::Accept {
+                version,
+                capabilities: accepted,
+            } => {
+                println!("arbiter accepted protocol version {version} with capabilities [{accepted} ]");
+                capabilities = accepted;
+            }
+            ArbiterMessage::Game { fen, red_turn } => {
+                let board = Board::from_fen(&fen).ok_or("invalid game position")?;
+                start = Game::new(board, red_turn);
+                moves.clear();
+
+                if engine.uci {
+                    engine.send("ucinewgame")?;
+                }
+                engine.synchronize()?;
+                stream.write(&PlayerMessage::Ready)?;
+            }
+            ArbiterMessage::Prompt { time } => {
+                let time = time.saturating_sub(arguments.margin).max(1);
+                think(engine, stream, &start, &moves, time, capabilities, &mut pending)?;
+            }
+            ArbiterMessage::Update { mv } => moves.push(mv),
+            ArbiterMessage::Result { conclusion } => println!("arbiter result {conclusion}"),
+            ArbiterMessage::Ping { token } => stream.write(&PlayerMessage::Pong { token })?,
+            ArbiterMessage::Stop | ArbiterMessage::Draw => {}
+            ArbiterMessage::Illegal { mv } => println!("arbiter rejected illegal move {mv}"),
+            ArbiterMessage::Session { token: session } => *token = Some(session),
+            ArbiterMessage::Error { error } => println!("arbiter could not decode a message: {error}"),
+            ArbiterMessage::Rejected { reason } => {
+                println!("arbiter rejected the connection because {reason}");
+                return Ok(());
+            }
+            // only sent to spectators
+            ArbiterMessage::Running { .. }
+            | ArbiterMessage::Listed
+            | ArbiterMessage::Watching { .. }
+            | ArbiterMessage::Missing { .. }
+            | ArbiterMessage::Clock { .. }
+            | ArbiterMessage::Analysis { .. } => {}
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let arguments = Arguments::parse();
+    let mut engine = Engine::spawn(&arguments)?;
+
+    let name = arguments
+        .name
+        .clone()
+        .or(engine.name.clone())
+        .unwrap_or("engine".to_owned());
+    let address = SocketAddr::new(arguments.ip, arguments.port);
+    let mut token = None;
+
+    loop {
+        let stream = match TcpStream::connect(address) {
+            Ok(s) => s,
+            Err(_) => {
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+        };
+
+        // lost connection, return to reconnect unless the engine itself is gone
+        let stream = LineStream::new(stream);
+        match session(&mut engine, &stream, &arguments, &name, &mut token) {
+            Ok(()) => return Err("arbiter rejected the connection".into()),
+            Err(error) if engine.child.try_wait()?.is_some() => return Err(error),
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_info() {
+        let analysis = Engine::parse_info("info depth 7 seldepth 9 score cp -35 nodes 12000 nps 60000 pv h2e2 h9g7");
+        let pv = ["h2e2", "h9g7"].map(|mv| mv.parse().unwrap());
+        assert_eq!(
+            analysis,
+            Some(Analysis {
+                depth: 7,
+                score: -35,
+                nodes: Some(12000),
+                nps: Some(60000),
+                pv: pv.to_vec(),
+            })
+        );
+
+        // mates closer to the root score further from zero, for both the side to move and its opponent
+        let score = |line| Engine::parse_info(line).map(|analysis| analysis.score);
+        assert_eq!(score("info depth 5 score mate 3"), Some(Engine::MATE_SCORE - 3));
+        assert_eq!(
+            score("info depth 5 score mate -2 pv a0a1"),
+            Some(2 - Engine::MATE_SCORE)
+        );
+        assert_eq!(score("info depth 5 score 120"), Some(120));
+
+        // lines without a depth or a score are not an analysis
+        assert_eq!(Engine::parse_info("info nodes 100 score cp 5"), None);
+        assert_eq!(Engine::parse_info("info depth 3 string hello"), None);
+        assert_eq!(Engine::parse_info("info depth 3 score cp"), None);
+    }
+
+    #[test]
+    fn go_commands() {
+        let start = Game::opening();
+        let fen = notation::fen(&start);
+
+        let [position, go] = Engine::go_commands(true, &start, &[], 900);
+        assert_eq!(position, format!("position fen {fen}"));
+        assert_eq!(go, "go movetime 900");
+
+        let moves = ["h2e2", "h9g7"].map(|mv| mv.parse().unwrap());
+        let [position, go] = Engine::go_commands(false, &start, &moves, 900);
+        assert_eq!(position, format!("position fen {fen} moves h2e2 h9g7"));
+        assert_eq!(go, "go time 900 movestogo 1");
+    }
+}
@@ -0,0 +1,57 @@
+use clap::Parser;
+use frontend::conformance;
+use std::process::{Command, ExitCode, Stdio};
+
+// runs the conformance checks against a client, such as
+//
+//     conformance -- target/release/player --port {port}
+#[derive(Parser, Debug)]
+struct Arguments {
+    #[arg(long, help = "Show what the client prints instead of discarding it")]
+    verbose: bool,
+
+    #[arg(
+        last = true,
+        required = true,
+        help = "Command that starts the client, with {port} replaced by the port of the mock arbiter"
+    )]
+    command: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let arguments = Arguments::parse();
+
+    let spawn = |port: u16| {
+        let mut parts = arguments
+            .command
+            .iter()
+            .map(|part| part.replace("{port}", &port.to_string()));
+        let mut command = Command::new(parts.next().unwrap());
+        command.args(parts);
+
+        if !arguments.verbose {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        command.spawn()
+    };
+
+    let mut failed = 0;
+    for (name, result) in conformance::run(spawn) {
+        match result {
+            Ok(true) => println!("{name:>12}  passed"),
+            Ok(false) => println!("{name:>12}  skipped as the client did not negotiate the capability it needs"),
+            Err(error) => {
+                println!("{name:>12}  failed: {error}");
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 {
+        println!("all checks passed");
+        ExitCode::SUCCESS
+    } else {
+        println!("{failed} of {} checks failed", conformance::CHECKS.len());
+        ExitCode::FAILURE
+    }
+}
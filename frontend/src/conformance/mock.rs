@@ -0,0 +1,246 @@
+use crate::line_stream::LineStream;
+use crate::protocol::{
+    ArbiterMessage, Capabilities, Capability, PROTOCOL_VERSION, PlayerMessage, Protocol, ProtocolError,
+};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+// how long either mock waits for a connection or message before failing
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+// the session token given to clients that negotiated resume
+pub const SESSION: &str = "mock";
+
+// stands in for the arbiter on a loopback port, with each client connection served by a script of sends and receives
+// that fail with a description of what happened instead
+pub struct MockArbiter {
+    listener: TcpListener,
+    stream: Option<LineStream>,
+    pub timeout: Duration,
+}
+
+// what a client told the arbiter during the handshake
+#[derive(Debug)]
+pub struct Handshake {
+    pub name: String,
+    pub version: u32,
+    pub capabilities: Capabilities, // the ones negotiated, which is none for version 1
+    pub key: Option<String>,
+    pub resumed: Option<String>, // the session token if the client resumed instead of sending info
+}
+
+impl MockArbiter {
+    pub fn bind() -> Result<Self, String> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .map_err(|error| format!("failed to bind mock arbiter: {error}"))?;
+        listener.set_nonblocking(true).map_err(|error| error.to_string())?;
+
+        Ok(Self {
+            listener,
+            stream: None,
+            timeout: TIMEOUT,
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map_or(0, |address| address.port())
+    }
+
+    // waits for the next connection, which replaces the current one
+    pub fn accept(&mut self) -> Result<(), String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false).map_err(|error| error.to_string())?;
+                    self.stream = Some(LineStream::new(stream));
+                    return Ok(());
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock && Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Err("client did not connect".to_owned()),
+                Err(error) => return Err(format!("failed to accept client: {error}")),
+            }
+        }
+    }
+
+    // closes the current connection as if the arbiter went away
+    pub fn disconnect(&mut self) {
+        self.stream = None;
+    }
+
+    pub fn send(&self, message: &ArbiterMessage) -> Result<(), String> {
+        self.send_line(&Protocol::encode_arbiter(message))
+    }
+
+    pub fn send_line(&self, line: &str) -> Result<(), String> {
+        let stream = self.stream.as_ref().ok_or("client is not connected")?;
+        stream
+            .write_line(line.to_owned())
+            .map_err(|error| format!("failed to send '{line}': {error}"))
+    }
+
+    pub fn recv(&self) -> Result<PlayerMessage, String> {
+        self.recv_before(Instant::now() + self.timeout)
+    }
+
+    pub fn recv_before(&self, deadline: Instant) -> Result<PlayerMessage, String> {
+        self.receive(deadline, Protocol::decode_player)
+    }
+
+    fn receive(
+        &self,
+        deadline: Instant,
+        decode: fn(&str) -> Result<PlayerMessage, ProtocolError>,
+    ) -> Result<PlayerMessage, String> {
+        let stream = self.stream.as_ref().ok_or("client is not connected")?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let line = stream
+            .read_line_within(timeout)
+            .map_err(|error| format!("no message from client: {error}"))?;
+        decode(&line).map_err(|error| format!("client sent '{line}': {error}"))
+    }
+
+    // the next message that is not search analysis, which players may send any number of during their turn
+    pub fn recv_decision(&self, deadline: Instant) -> Result<PlayerMessage, String> {
+        loop {
+            match self.recv_before(deadline)? {
+                PlayerMessage::Analysis { .. } => {}
+                message => return Ok(message),
+            }
+        }
+    }
+
+    // plays the arbiter side of the handshake, offering the given capabilities
+    pub fn handshake(&self, capabilities: Capabilities) -> Result<Handshake, String> {
+        let recv = || self.receive(Instant::now() + self.timeout, Protocol::decode_handshake);
+        let PlayerMessage::Init {
+            version,
+            capabilities: offered,
+        } = recv()?
+        else {
+            return Err("client did not start with init".to_owned());
+        };
+
+        let capabilities = match version {
+            0 => return Err("client sent protocol version 0".to_owned()),
+            1 => Capabilities::none(),
+            _ => {
+                let capabilities = offered.intersect(capabilities);
+                let version = version.min(PROTOCOL_VERSION);
+                self.send(&ArbiterMessage::Accept { version, capabilities })?;
+                capabilities
+            }
+        };
+
+        let mut message = recv()?;
+        let mut key = None;
+        if let PlayerMessage::Auth { key: given } = message {
+            key = Some(given);
+            message = recv()?;
+        }
+
+        let (name, resumed) = match message {
+            PlayerMessage::Info { name } => (name, None),
+            PlayerMessage::Resume { name, token } if capabilities.contains(Capability::Resume) => (name, Some(token)),
+            message => {
+                let line = Protocol::encode_player(&message);
+                return Err(format!("client sent '{line}' instead of info"));
+            }
+        };
+
+        if capabilities.contains(Capability::Resume) {
+            let token = SESSION.to_owned();
+            self.send(&ArbiterMessage::Session { token })?;
+        }
+
+        Ok(Handshake {
+            name,
+            version: version.min(PROTOCOL_VERSION),
+            capabilities,
+            key,
+            resumed,
+        })
+    }
+}
+
+// stands in for a player connected to an arbiter, with the same kind of scripting as the mock arbiter
+pub struct MockPlayer {
+    stream: LineStream,
+    pub timeout: Duration,
+}
+
+impl MockPlayer {
+    pub fn connect(address: SocketAddr) -> Result<Self, String> {
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)
+            .map_err(|error| format!("failed to connect to arbiter at {address}: {error}"))?;
+
+        Ok(Self {
+            stream: LineStream::new(stream),
+            timeout: TIMEOUT,
+        })
+    }
+
+    pub fn send(&self, message: &PlayerMessage) -> Result<(), String> {
+        self.send_line(&Protocol::encode_player(message))
+    }
+
+    pub fn send_line(&self, line: &str) -> Result<(), String> {
+        self.stream
+            .write_line(line.to_owned())
+            .map_err(|error| format!("failed to send '{line}': {error}"))
+    }
+
+    pub fn recv(&self) -> Result<ArbiterMessage, String> {
+        let line = self
+            .stream
+            .read_line_within(self.timeout)
+            .map_err(|error| format!("no message from arbiter: {error}"))?;
+        Protocol::decode_arbiter(&line).map_err(|error| format!("arbiter sent '{line}': {error}"))
+    }
+
+    // plays the player side of the handshake, returning the capabilities the arbiter accepted
+    pub fn handshake(&self, name: &str, capabilities: Capabilities) -> Result<Capabilities, String> {
+        let version = PROTOCOL_VERSION;
+        self.send(&PlayerMessage::Init { version, capabilities })?;
+        let ArbiterMessage::Accept { capabilities, .. } = self.recv()? else {
+            return Err("arbiter did not accept the connection".to_owned());
+        };
+
+        let name = name.to_owned();
+        self.send(&PlayerMessage::Info { name })?;
+        Ok(capabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake() {
+        let mut arbiter = MockArbiter::bind().unwrap();
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, arbiter.port()));
+
+        let player = std::thread::spawn(move || {
+            let player = MockPlayer::connect(address)?;
+            let capabilities = player.handshake("mock", Capabilities::from_iter([Capability::Ping]))?;
+            assert!(capabilities.contains(Capability::Ping));
+            match player.recv()? {
+                ArbiterMessage::Ping { token: 7 } => player.send(&PlayerMessage::Pong { token: 7 }),
+                _ => Err("expected ping".to_owned()),
+            }
+        });
+
+        arbiter.accept().unwrap();
+        let handshake = arbiter.handshake(Capabilities::all()).unwrap();
+        assert_eq!(handshake.name, "mock");
+        assert_eq!(handshake.capabilities, Capabilities::from_iter([Capability::Ping]));
+
+        arbiter.send(&ArbiterMessage::Ping { token: 7 }).unwrap();
+        assert!(matches!(arbiter.recv(), Ok(PlayerMessage::Pong { token: 7 })));
+        player.join().unwrap().unwrap();
+    }
+}
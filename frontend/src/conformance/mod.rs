@@ -0,0 +1,242 @@
+use crate::conformance::mock::{Handshake, MockArbiter, SESSION};
+use crate::protocol::{ArbiterMessage, Capabilities, Capability, Conclusion, PlayerMessage, Protocol, Reason, Verdict};
+use chinese_chess::game::Game;
+use chinese_chess::notation;
+use std::process::Child;
+use std::time::{Duration, Instant};
+
+pub mod mock;
+
+// time given to the client for each move, and how much longer it may take before the check fails
+const MOVE_TIME: u32 = 500;
+const GRACE_TIME: Duration = Duration::from_secs(10);
+
+// one behavior a client must show when connected to a mock arbiter as a player, returning false if it was skipped
+// because the client did not negotiate the capability it needs
+pub struct Check {
+    pub name: &'static str,
+    run: fn(&mut MockArbiter, &Handshake) -> Result<bool, String>,
+}
+
+pub const CHECKS: [Check; 9] = [
+    Check::new("handshake", handshake),
+    Check::new("game", game),
+    Check::new("prompt", prompt),
+    Check::new("update", update),
+    Check::new("result", result),
+    Check::new("malformed", malformed),
+    Check::new("ping", ping),
+    Check::new("stop", stop),
+    Check::new("disconnect", disconnect),
+];
+
+impl Check {
+    const fn new(name: &'static str, run: fn(&mut MockArbiter, &Handshake) -> Result<bool, String>) -> Self {
+        Self { name, run }
+    }
+}
+
+// runs every check against a new client, started by spawn with the port of the mock arbiter to connect to
+pub fn run(spawn: impl Fn(u16) -> std::io::Result<Child>) -> Vec<(&'static str, Result<bool, String>)> {
+    CHECKS
+        .iter()
+        .map(|check| {
+            let result = (|| {
+                let mut arbiter = MockArbiter::bind()?;
+                let mut client =
+                    ClientGuard(spawn(arbiter.port()).map_err(|error| format!("failed to start client: {error}"))?);
+
+                arbiter.accept()?;
+                let handshake = arbiter.handshake(Capabilities::all())?;
+                let result = (check.run)(&mut arbiter, &handshake);
+
+                if let Ok(Some(status)) = client.0.try_wait() {
+                    return Err(format!("client exited with {status}"));
+                }
+                result
+            })();
+            (check.name, result)
+        })
+        .collect()
+}
+
+// kills the client once the check is over, however it ended
+struct ClientGuard(Child);
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn handshake(arbiter: &mut MockArbiter, handshake: &Handshake) -> Result<bool, String> {
+    if handshake.name.is_empty() {
+        return Err("client sent an empty name".to_owned());
+    }
+
+    // an idle client should not say anything until it is given a game
+    arbiter.timeout = Duration::from_millis(500);
+    match arbiter.recv() {
+        Ok(message) => Err(format!("idle client sent '{}'", Protocol::encode_player(&message))),
+        Err(_) => Ok(true),
+    }
+}
+
+fn game(arbiter: &mut MockArbiter, _: &Handshake) -> Result<bool, String> {
+    start(arbiter, &Game::opening())?;
+    Ok(true)
+}
+
+fn prompt(arbiter: &mut MockArbiter, _: &Handshake) -> Result<bool, String> {
+    let game = Game::opening();
+    start(arbiter, &game)?;
+    play(arbiter, &game)?;
+    Ok(true)
+}
+
+// the client has to follow the moves of the game to find a legal move for the position it is prompted in
+fn update(arbiter: &mut MockArbiter, _: &Handshake) -> Result<bool, String> {
+    let mut game = Game::opening();
+    start(arbiter, &game)?;
+
+    for mv in ["h2e2", "h9g7", "b0c2"] {
+        let mv = notation::parse_iccs(mv).unwrap();
+        arbiter.send(&ArbiterMessage::Update { mv })?;
+        game.make_move(mv);
+    }
+
+    play(arbiter, &game)?;
+    Ok(true)
+}
+
+// the client has to forget the previous game once it is over and start the next one from its own position
+fn result(arbiter: &mut MockArbiter, handshake: &Handshake) -> Result<bool, String> {
+    let mut game = Game::opening();
+    start(arbiter, &game)?;
+
+    let mv = notation::parse_iccs("h2e2").unwrap();
+    arbiter.send(&ArbiterMessage::Update { mv })?;
+    game.make_move(mv);
+
+    if handshake.capabilities.contains(Capability::Result) {
+        let conclusion = Conclusion::new(Verdict::Draw, Reason::Agreement);
+        arbiter.send(&ArbiterMessage::Result { conclusion })?;
+    }
+
+    let game = Game::opening();
+    start(arbiter, &game)?;
+    play(arbiter, &game)?;
+    Ok(true)
+}
+
+// lines that cannot be decoded, from garbage to known messages with bad arguments, should be skipped
+fn malformed(arbiter: &mut MockArbiter, _: &Handshake) -> Result<bool, String> {
+    let lines = [
+        "castle kingside",
+        "prompt soon",
+        "update h2x2",
+        "game",
+        "result red",
+        "  \t ",
+        "ping many",
+    ];
+    for line in lines {
+        arbiter.send_line(line)?;
+    }
+
+    let game = Game::opening();
+    start(arbiter, &game)
+        .and_then(|_| play(arbiter, &game))
+        .map_err(|error| format!("after malformed lines, {error}"))?;
+    Ok(true)
+}
+
+fn ping(arbiter: &mut MockArbiter, handshake: &Handshake) -> Result<bool, String> {
+    if !handshake.capabilities.contains(Capability::Ping) {
+        return Ok(false);
+    }
+
+    arbiter.send(&ArbiterMessage::Ping { token: 42 })?;
+    match arbiter.recv()? {
+        PlayerMessage::Pong { token: 42 } => Ok(true),
+        message => Err(format!(
+            "client sent '{}' instead of pong",
+            Protocol::encode_player(&message)
+        )),
+    }
+}
+
+// a client told to stop should move right away instead of using all of its time
+fn stop(arbiter: &mut MockArbiter, handshake: &Handshake) -> Result<bool, String> {
+    if !handshake.capabilities.contains(Capability::Stop) {
+        return Ok(false);
+    }
+
+    let game = Game::opening();
+    start(arbiter, &game)?;
+    arbiter.send(&ArbiterMessage::Prompt { time: 600_000 })?;
+    std::thread::sleep(Duration::from_millis(MOVE_TIME as u64));
+    arbiter.send(&ArbiterMessage::Stop)?;
+
+    decide(arbiter, &game, Instant::now() + GRACE_TIME).map_err(|error| format!("after stop, {error}"))?;
+    Ok(true)
+}
+
+// a client that lost its connection should connect again, and resume its session if it can
+fn disconnect(arbiter: &mut MockArbiter, handshake: &Handshake) -> Result<bool, String> {
+    arbiter.disconnect();
+    arbiter
+        .accept()
+        .map_err(|_| "client did not reconnect after the arbiter closed the connection".to_owned())?;
+
+    let reconnected = arbiter.handshake(Capabilities::all())?;
+    if reconnected.name != handshake.name {
+        return Err(format!(
+            "client reconnected as '{}' instead of '{}'",
+            reconnected.name, handshake.name
+        ));
+    }
+
+    if handshake.capabilities.contains(Capability::Resume) && reconnected.resumed.as_deref() != Some(SESSION) {
+        return Err("client did not resume its session after reconnecting".to_owned());
+    }
+
+    let game = Game::opening();
+    start(arbiter, &game)?;
+    play(arbiter, &game)?;
+    Ok(true)
+}
+
+fn start(arbiter: &MockArbiter, game: &Game) -> Result<(), String> {
+    arbiter.send(&ArbiterMessage::from_game(game))?;
+    match arbiter.recv()? {
+        PlayerMessage::Ready => Ok(()),
+        message => Err(format!(
+            "client sent '{}' instead of ready",
+            Protocol::encode_player(&message)
+        )),
+    }
+}
+
+fn play(arbiter: &MockArbiter, game: &Game) -> Result<(), String> {
+    arbiter.send(&ArbiterMessage::Prompt { time: MOVE_TIME })?;
+    let deadline = Instant::now() + Duration::from_millis(MOVE_TIME as u64) + GRACE_TIME;
+    decide(arbiter, game, deadline)
+}
+
+// a draw offer may come before the move, and resigning is always a valid answer
+fn decide(arbiter: &MockArbiter, game: &Game, deadline: Instant) -> Result<(), String> {
+    loop {
+        match arbiter.recv_decision(deadline)? {
+            PlayerMessage::Play { mv } if game.can_move(mv) => return Ok(()),
+            PlayerMessage::Play { mv } => return Err(format!("client played illegal move {mv}")),
+            PlayerMessage::Resign | PlayerMessage::Draw { accept: true } => return Ok(()),
+            PlayerMessage::Draw { accept: false } => {}
+            message => {
+                let line = Protocol::encode_player(&message);
+                return Err(format!("client sent '{line}' instead of a move"));
+            }
+        }
+    }
+}
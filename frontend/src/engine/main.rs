@@ -0,0 +1,290 @@
+use chinese_chess::game::Game;
+use chinese_chess::notation;
+use chinese_chess::ranker::Ranker;
+use clap::Parser;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+// speaks ucci, or the xiangqi flavor of uci, on standard input and output so the ranker can be used by other software
+#[derive(Parser, Debug)]
+struct Arguments {
+    #[arg(
+        short,
+        long,
+        default_value_t = 4,
+        help = "Depth to search to when go is given no limit"
+    )]
+    depth: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Dialect {
+    Ucci,
+    Uci,
+}
+
+#[derive(Default, Debug)]
+struct Limits {
+    depth: Option<u32>,
+    nodes: Option<u32>,
+    time: Option<Duration>,
+    infinite: bool,
+}
+
+struct Engine {
+    dialect: Dialect,
+    game: Game,
+    default_depth: u32,
+    pending: VecDeque<String>,
+    quit: bool,
+    stop: Arc<AtomicBool>, // set by the reader as soon as stop or quit arrives, cleared once the line is handled
+}
+
+impl Engine {
+    const NAME: &'static str = "chinese-chess";
+    const MAX_DEPTH: u32 = 64;
+
+    fn handle(&mut self, line: &str, input: &Receiver<String>) {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("ucci") => self.greet(Dialect::Ucci),
+            Some("uci") => self.greet(Dialect::Uci),
+            Some("isready") => println!("readyok"),
+            Some("position") => match parse_position(tokens) {
+                Some(game) => self.game = game,
+                None => eprintln!("invalid position '{line}'"),
+            },
+            Some("go") => {
+                let limits = parse_limits(tokens, self.game.red_turn());
+                self.search(limits, input);
+            }
+            Some("quit") => {
+                self.stop.store(false, Ordering::Relaxed);
+                self.quit = true;
+            }
+            Some("stop") => self.stop.store(false, Ordering::Relaxed),
+            Some("setoption" | "ucinewgame" | "ponderhit" | "probe" | "banmoves") | None => {}
+            Some(_) => eprintln!("unknown command '{line}'"),
+        }
+    }
+
+    fn greet(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+        println!("id name {}", Self::NAME);
+
+        match dialect {
+            Dialect::Ucci => println!("ucciok"),
+            Dialect::Uci => println!("uciok"),
+        }
+    }
+
+    fn search(&mut self, limits: Limits, input: &Receiver<String>) {
+        let start = Instant::now();
+        let mut ranker = Ranker::new(self.game.clone());
+        ranker.set_stop(Some(self.stop.clone()));
+        ranker.set_deadline(limits.time.map(|time| start + time));
+
+        let max_depth = match limits {
+            Limits { depth: Some(depth), .. } => depth.min(Self::MAX_DEPTH),
+            Limits {
+                nodes: None,
+                time: None,
+                infinite: false,
+                ..
+            } => self.default_depth,
+            _ => Self::MAX_DEPTH,
+        };
+
+        let mut stopped = self.game.iter_moves().next().is_none();
+
+        while !stopped && ranker.depth() < max_depth {
+            // the search gives up midway on stop or once the time is up, in which case the depth is not reported
+            let depth = ranker.depth();
+            ranker.deeper();
+            if ranker.depth() == depth {
+                break;
+            }
+
+            let pv = ranker.variation().map(|mv| mv.to_string()).collect::<Vec<_>>();
+            println!(
+                "info depth {} score {} nodes {} time {} pv {}",
+                ranker.depth(),
+                ranker.score(),
+                ranker.evaluated(),
+                start.elapsed().as_millis(),
+                pv.join(" ")
+            );
+
+            while let Ok(line) = input.try_recv() {
+                stopped |= self.interrupt(line);
+            }
+
+            let nodes = limits.nodes.is_some_and(|nodes| ranker.evaluated() >= nodes);
+            stopped |= nodes || ranker.stopped();
+        }
+
+        // an infinite search only reports its move once told to stop
+        while limits.infinite && !stopped {
+            match input.recv() {
+                Ok(line) => stopped = self.interrupt(line),
+                Err(_) => break,
+            }
+        }
+
+        match (ranker.best(), self.dialect) {
+            (Some(best), _) => println!("bestmove {best}"),
+            (None, Dialect::Ucci) => println!("nobestmove"),
+            (None, Dialect::Uci) => println!("bestmove 0000"),
+        }
+    }
+
+    // only stop, quit and isready are expected during a search, anything else waits until it finishes along with
+    // everything that comes after it
+    fn interrupt(&mut self, line: String) -> bool {
+        if !self.pending.is_empty() {
+            self.pending.push_back(line);
+            return false;
+        }
+
+        match line.split_whitespace().next() {
+            Some("stop") => {
+                self.stop.store(false, Ordering::Relaxed);
+                true
+            }
+            Some("quit") => {
+                self.stop.store(false, Ordering::Relaxed);
+                self.quit = true;
+                true
+            }
+            Some("isready") => {
+                println!("readyok");
+                false
+            }
+            _ => {
+                self.pending.push_back(line);
+                false
+            }
+        }
+    }
+}
+
+// position {fen <fen> | startpos} [moves <move>...]
+fn parse_position<'a>(mut tokens: impl Iterator<Item = &'a str>) -> Option<Game> {
+    let game = match tokens.next()? {
+        "startpos" => match tokens.next() {
+            Some("moves") | None => Game::opening(),
+            Some(_) => return None,
+        },
+        "fen" => {
+            // the fen spans several tokens and ends where the moves start
+            let fen = tokens
+                .by_ref()
+                .take_while(|&token| token != "moves")
+                .collect::<Vec<_>>();
+            notation::parse_fen(&fen.join(" "))?
+        }
+        _ => return None,
+    };
+
+    apply_moves(game, tokens)
+}
+
+fn apply_moves<'a>(mut game: Game, tokens: impl Iterator<Item = &'a str>) -> Option<Game> {
+    for token in tokens {
+        let mv = notation::parse_iccs(token).and_then(|mv| notation::validate(&game, mv));
+        match mv {
+            Ok(mv) => game.make_move(mv),
+            Err(error) => {
+                eprintln!("invalid move in position: {error}");
+                return None;
+            }
+        }
+    }
+    Some(game)
+}
+
+// go [depth <n>] [nodes <n>] [time <ms> [movestogo <n>] [increment <ms>]] [movetime <ms>] [infinite] as well as the
+// uci clock arguments, of which only the ones for the side to move are used
+fn parse_limits<'a>(mut tokens: impl Iterator<Item = &'a str>, red: bool) -> Limits {
+    let mut limits = Limits::default();
+    let mut clock = None;
+    let mut increment = 0;
+    let mut moves_to_go = None;
+
+    while let Some(token) = tokens.next() {
+        let mut value = || tokens.next().and_then(|value| value.parse::<u64>().ok());
+        match token {
+            "depth" => limits.depth = value().map(|value| value as u32),
+            "nodes" => limits.nodes = value().map(|value| value as u32),
+            "movetime" => limits.time = value().map(Duration::from_millis),
+            "time" => clock = value(),
+            "increment" => increment = value().unwrap_or(0),
+            "wtime" | "btime" if (token == "wtime") == red => clock = value(),
+            "winc" | "binc" if (token == "winc") == red => increment = value().unwrap_or(0),
+            "movestogo" => moves_to_go = value(),
+            "infinite" => limits.infinite = true,
+            "wtime" | "btime" | "winc" | "binc" | "opptime" | "oppmovestogo" | "oppincrement" => _ = value(),
+            _ => {}
+        }
+    }
+
+    if limits.time.is_none()
+        && let Some(clock) = clock
+    {
+        // spread the remaining time over the moves left, assuming a fixed number if unknown
+        let moves = moves_to_go.unwrap_or(30).max(1);
+        limits.time = Some(Duration::from_millis(clock / moves + increment));
+    }
+
+    limits
+}
+
+fn main() {
+    let arguments = Arguments::parse();
+
+    // read on a separate thread so stop can be received while searching, and interrupt the search right away
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let stop = Arc::new(AtomicBool::new(false));
+    let reader_stop = stop.clone();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if matches!(line.split_whitespace().next(), Some("stop" | "quit")) {
+                reader_stop.store(true, Ordering::Relaxed);
+            }
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut engine = Engine {
+        dialect: Dialect::Ucci,
+        game: Game::opening(),
+        default_depth: arguments.depth,
+        pending: VecDeque::new(),
+        quit: false,
+        stop,
+    };
+
+    while !engine.quit {
+        let line = match engine.pending.pop_front() {
+            Some(line) => line,
+            None => match receiver.recv() {
+                Ok(line) => line,
+                Err(_) => break,
+            },
+        };
+
+        engine.handle(&line, &receiver);
+    }
+
+    // nobody is left to read the farewell if standard input closed instead
+    if engine.quit && engine.dialect == Dialect::Ucci {
+        println!("bye");
+    }
+}
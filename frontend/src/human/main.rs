@@ -2,13 +2,17 @@ use chinese_chess::board::Board;
 use chinese_chess::display_format::DisplayFormat;
 use chinese_chess::game::Game;
 use chinese_chess::location::{Location, Move};
+use chinese_chess::pgn::Pgn;
 use chinese_chess::piece::{Piece, PieceKind};
 use clap::Parser;
 use eframe::egui;
 use frontend::line_stream::AsyncLineStream;
-use frontend::protocol::{ArbiterMessage, PlayerMessage, Protocol};
+use frontend::protocol::{
+    Analysis, ArbiterMessage, Capabilities, Capability, Conclusion, PROTOCOL_VERSION, PlayerMessage, Protocol,
+};
 use smol::channel::{Receiver, Sender};
 use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::time::Duration;
 
@@ -22,6 +26,39 @@ struct Arguments {
 
     #[arg(short, long, default_value = "human")]
     name: String,
+
+    #[arg(short, long, help = "Key to authenticate with if the arbiter requires one")]
+    key: Option<String>,
+
+    #[arg(long, help = "Review a game from a PGN file instead of connecting to an arbiter")]
+    review: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Which game in the PGN file to review, starting from 1"
+    )]
+    index: usize,
+
+    #[arg(
+        long,
+        help = "Watch a running game on the arbiter instead of playing, or 0 to keep watching whichever game is running"
+    )]
+    watch: Option<u32>,
+}
+
+// a running game on the arbiter that is being watched instead of played
+#[derive(Default)]
+struct Spectating {
+    title: String,
+    clock: Option<(u32, u32)>,       // milliseconds used by red and black
+    analysis: [Option<Analysis>; 2], // the latest search of red and black
+}
+
+// a finished game loaded from a file that can be stepped through
+struct Review {
+    title: String,
+    moves: Vec<Move>,
 }
 
 struct Application {
@@ -30,6 +67,12 @@ struct Application {
     sender: Sender<PlayerMessage>,
     selected_location: Option<Location>,
     pending_moves: Vec<Move>,
+    capabilities: Capabilities,
+    draw_offered: bool,
+    conclusion: Option<Conclusion>,
+    review: Option<Review>,
+    spectating: Option<Spectating>,
+    rejected: Option<String>, // why the arbiter turned the connection away, which is not retried
 }
 
 impl Application {
@@ -45,9 +88,30 @@ impl Application {
             sender,
             selected_location: None,
             pending_moves: Vec::new(),
+            capabilities: Capabilities::none(),
+            draw_offered: false,
+            conclusion: None,
+            review: None,
+            spectating: None,
+            rejected: None,
         }
     }
 
+    fn with_review(mut self, pgn: Pgn) -> Self {
+        let header = |key| pgn.header(key).unwrap_or("?");
+        let title = format!("{} vs. {} ({})", header("Red"), header("Black"), header("Result"));
+        let moves = pgn.game().history().iter().map(|&(mv, _)| mv).collect();
+
+        self.game = Some(pgn.start());
+        self.review = Some(Review { title, moves });
+        self
+    }
+
+    fn spectating(mut self) -> Self {
+        self.spectating = Some(Spectating::default());
+        self
+    }
+
     fn draw_board(&mut self, ui: &mut egui::Ui) {
         let cell_size = 60.0;
         let margin_x = 25.0;
@@ -152,7 +216,6 @@ impl Application {
         }
 
         // Draw pieces
-        let manifest_dir = env!("CARGO_MANIFEST_DIR");
         for x in 0..Board::WIDTH {
             for y in 0..Board::HEIGHT {
                 let loc = Location::from_xy(x, y).unwrap();
@@ -201,8 +264,7 @@ impl Application {
 
             if let Some(mv) = self.pending_moves.iter().find(|m| m.from == from && m.to == loc) {
                 let _ = self.sender.try_send(PlayerMessage::Play { mv: *mv });
-                self.pending_moves.clear();
-                self.selected_location = None;
+                self.end_turn();
             } else if let Some(game) = &self.game {
                 if game.board()[loc].is_some() {
                     self.selected_location = Some(loc);
@@ -216,6 +278,93 @@ impl Application {
             self.selected_location = Some(loc);
         }
     }
+
+    fn end_turn(&mut self) {
+        self.pending_moves.clear();
+        self.selected_location = None;
+    }
+
+    fn draw_review(&mut self, ui: &mut egui::Ui) {
+        let (Some(review), Some(game)) = (&self.review, &mut self.game) else {
+            return;
+        };
+        let ply = game.history().len();
+
+        ui.label(&review.title);
+        ui.horizontal(|ui| {
+            if ui.add_enabled(ply > 0, egui::Button::new("|<")).clicked() {
+                while !game.history().is_empty() {
+                    game.undo_move();
+                }
+            }
+
+            if ui.add_enabled(ply > 0, egui::Button::new("<")).clicked() {
+                game.undo_move();
+            }
+
+            if ui
+                .add_enabled(ply < review.moves.len(), egui::Button::new(">"))
+                .clicked()
+            {
+                game.make_move(review.moves[ply]);
+            }
+
+            if ui
+                .add_enabled(ply < review.moves.len(), egui::Button::new(">|"))
+                .clicked()
+            {
+                for &mv in &review.moves[ply..] {
+                    game.make_move(mv);
+                }
+            }
+
+            ui.label(format!("{ply} / {}", review.moves.len()));
+        });
+    }
+
+    fn draw_spectating(&self, ui: &mut egui::Ui) {
+        let Some(spectating) = &self.spectating else {
+            return;
+        };
+
+        ui.label(&spectating.title);
+        if let Some((red, black)) = spectating.clock {
+            let seconds = |milliseconds: u32| milliseconds as f32 / 1000.0;
+            ui.label(format!("red {:.1}s black {:.1}s", seconds(red), seconds(black)));
+        }
+
+        for (side, analysis) in ["red", "black"].iter().zip(&spectating.analysis) {
+            if let Some(analysis) = analysis {
+                ui.label(format!("{side} {analysis}"));
+            }
+        }
+    }
+
+    fn draw_actions(&mut self, ui: &mut egui::Ui) {
+        // the arbiter only reads these during our turn, so they are only offered while prompted
+        let prompted = !self.pending_moves.is_empty();
+
+        ui.horizontal(|ui| {
+            if self.capabilities.contains(Capability::Resign)
+                && ui.add_enabled(prompted, egui::Button::new("Resign")).clicked()
+            {
+                let _ = self.sender.try_send(PlayerMessage::Resign);
+                self.end_turn();
+            }
+
+            if self.capabilities.contains(Capability::Draw) {
+                if self.draw_offered {
+                    if ui.add_enabled(prompted, egui::Button::new("Accept draw")).clicked() {
+                        let _ = self.sender.try_send(PlayerMessage::Draw { accept: true });
+                        self.draw_offered = false;
+                        self.end_turn();
+                    }
+                } else if ui.add_enabled(prompted, egui::Button::new("Offer draw")).clicked() {
+                    let _ = self.sender.try_send(PlayerMessage::Draw { accept: false });
+                }
+            }
+        });
+    }
 }
 
 impl eframe::App for Application {
@@ -226,12 +375,17 @@ impl eframe::App for Application {
                     let board = Board::from_fen(&fen).unwrap();
                     self.game = Some(Game::new(board, red_turn));
                     self.selected_location = None;
-                    let _ = self.sender.try_send(PlayerMessage::Ready);
+                    self.draw_offered = false;
+                    self.conclusion = None;
+                    if self.spectating.is_none() {
+                        let _ = self.sender.try_send(PlayerMessage::Ready);
+                    }
                 }
                 ArbiterMessage::Update { mv } => {
                     if let Some(game) = &mut self.game {
                         game.make_move(mv);
                     }
+                    self.draw_offered = false;
                 }
                 ArbiterMessage::Prompt { .. } => {
                     if let Some(game) = &self.game {
@@ -239,6 +393,44 @@ impl eframe::App for Application {
                         game.fill_moves(&mut self.pending_moves);
                     }
                 }
+                ArbiterMessage::Accept { capabilities, .. } => self.capabilities = capabilities,
+                ArbiterMessage::Draw => self.draw_offered = true,
+                ArbiterMessage::Result { conclusion } => {
+                    // keep the final position on screen for review until the next game starts
+                    self.conclusion = Some(conclusion);
+                    self.draw_offered = false;
+                    self.end_turn();
+                }
+                ArbiterMessage::Watching { id, red, black } => {
+                    if let Some(spectating) = &mut self.spectating {
+                        spectating.title = format!("Watching #{id} {red} vs. {black}");
+                        spectating.clock = None;
+                        spectating.analysis = [None, None];
+                    }
+                }
+                ArbiterMessage::Clock { red, black } => {
+                    if let Some(spectating) = &mut self.spectating {
+                        spectating.clock = Some((red, black));
+                    }
+                }
+                ArbiterMessage::Analysis { red, analysis } => {
+                    if let Some(spectating) = &mut self.spectating {
+                        spectating.analysis[if red { 0 } else { 1 }] = Some(analysis);
+                    }
+                }
+                ArbiterMessage::Missing { id } => {
+                    if let Some(spectating) = &mut self.spectating {
+                        spectating.title = format!("Game #{id} is not running");
+                    }
+                }
+                ArbiterMessage::Rejected { reason } => self.rejected = Some(reason),
+                ArbiterMessage::Error { error } => eprintln!("arbiter could not decode a message: {error}"),
+                ArbiterMessage::Ping { .. }
+                | ArbiterMessage::Stop
+                | ArbiterMessage::Illegal { .. }
+                | ArbiterMessage::Running { .. }
+                | ArbiterMessage::Listed
+                | ArbiterMessage::Session { .. } => {}
             }
         }
         ctx.request_repaint_after(Duration::from_millis(50));
@@ -266,7 +458,9 @@ impl eframe::App for Application {
                             status.push_str(&format!("({}) {} {} - ", game.history().len(), mv, piece));
                         }
 
-                        if let Some(outcome) = game.outcome() {
+                        if let Some(conclusion) = self.conclusion {
+                            status.push_str(&format!("game over: {conclusion}"));
+                        } else if let Some(outcome) = game.outcome() {
                             status.push_str(&format!("{}", outcome.display(format)));
                         } else {
                             let check = game.board().king_in_check(game.red_turn());
@@ -277,6 +471,9 @@ impl eframe::App for Application {
 
                         ui.label(status);
                         ui.label(game.board().fen());
+                        self.draw_actions(ui);
+                        self.draw_review(ui);
+                        self.draw_spectating(ui);
                     });
 
                     ui.add_space(20.0);
@@ -335,7 +532,12 @@ impl eframe::App for Application {
                 });
             } else {
                 ui.centered_and_justified(|ui| {
-                    ui.heading("Waiting for game to start...");
+                    let heading = match (&self.rejected, &self.spectating) {
+                        (Some(reason), _) => &format!("Rejected by the arbiter because {reason}"),
+                        (None, Some(spectating)) if !spectating.title.is_empty() => &spectating.title,
+                        _ => "Waiting for game to start...",
+                    };
+                    ui.heading(heading);
                 });
             }
         });
@@ -350,9 +552,19 @@ fn main() -> Result<(), eframe::Error> {
     let (tx_to_ui, rx_in_ui) = smol::channel::unbounded();
     let (tx_from_ui, rx_in_thread) = smol::channel::unbounded();
 
+    let review = args.review.as_ref().map(|path| load_review(path, args.index));
+
     let args_name = args.name.clone();
+    let reviewing = review.is_some();
+    let watch = args.watch;
+    let key = args.key.clone();
 
     thread::spawn(move || {
+        // a reviewed game is only browsed locally, so there is no connection to make
+        if reviewing {
+            return;
+        }
+
         smol::block_on(async {
             loop {
                 // Attempt to connect
@@ -367,28 +579,87 @@ fn main() -> Result<(), eframe::Error> {
                 let line_stream = AsyncLineStream::new(stream);
 
                 // Send init and info
+                let capabilities = Capabilities::from_iter([
+                    Capability::Result,
+                    Capability::Resign,
+                    Capability::Draw,
+                    Capability::Ping,
+                    Capability::Error,
+                ]);
                 let _ = line_stream
-                    .write_line(Protocol::encode_player(&PlayerMessage::Init { version: 1 }))
-                    .await;
-                let _ = line_stream
-                    .write_line(Protocol::encode_player(&PlayerMessage::Info {
-                        name: args_name.clone(),
+                    .write_line(Protocol::encode_player(&PlayerMessage::Init {
+                        version: PROTOCOL_VERSION,
+                        capabilities,
                     }))
                     .await;
 
+                if let Some(key) = key.clone() {
+                    let _ = line_stream
+                        .write_line(Protocol::encode_player(&PlayerMessage::Auth { key }))
+                        .await;
+                }
+
+                // a spectator asks for a game instead of registering as a player
+                let requests = match watch {
+                    None => vec![PlayerMessage::Info {
+                        name: args_name.clone(),
+                    }],
+                    Some(0) => vec![PlayerMessage::Spectate, PlayerMessage::List],
+                    Some(id) => vec![PlayerMessage::Spectate, PlayerMessage::Watch { id }],
+                };
+                for request in &requests {
+                    let _ = line_stream.write_line(Protocol::encode_player(request)).await;
+                }
+
                 // Race the read loop against the write loop
-                smol::future::race(
+                let rejected = smol::future::race(
                     async {
                         // Read loop
-                        while let Some(line) = line_stream.read_line().await {
-                            if let Some(msg) = Protocol::decode_arbiter(&line) {
-                                if tx_to_ui.send(msg).await.is_err() {
-                                    break;
+                        let mut found = false;
+                        while let Ok(line) = line_stream.read_line().await {
+                            let msg = match Protocol::decode_arbiter(&line) {
+                                Ok(msg) => msg,
+                                Err(error) => {
+                                    eprintln!("skipping arbiter message '{line}': {error}");
+                                    continue;
                                 }
-                            } else {
+                            };
+
+                            // when following whichever game is running, watch the first one listed and list again
+                            // once it is over, waiting a bit whenever nothing is running
+                            let reply = match &msg {
+                                &ArbiterMessage::Ping { token } => Some(PlayerMessage::Pong { token }),
+                                &ArbiterMessage::Running { id, .. } if !found => {
+                                    found = true;
+                                    Some(PlayerMessage::Watch { id })
+                                }
+                                ArbiterMessage::Listed if !found => {
+                                    smol::Timer::after(Duration::from_secs(1)).await;
+                                    Some(PlayerMessage::List)
+                                }
+                                ArbiterMessage::Result { .. } | ArbiterMessage::Missing { .. } if watch == Some(0) => {
+                                    found = false;
+                                    Some(PlayerMessage::List)
+                                }
+                                _ => None,
+                            };
+
+                            if let Some(reply) = reply
+                                && line_stream.write_line(Protocol::encode_player(&reply)).await.is_err()
+                            {
                                 break;
                             }
+
+                            let rejected = matches!(msg, ArbiterMessage::Rejected { .. });
+                            if !matches!(msg, ArbiterMessage::Ping { .. }) && tx_to_ui.send(msg).await.is_err() {
+                                break;
+                            }
+
+                            if rejected {
+                                return true;
+                            }
                         }
+                        false
                     },
                     async {
                         // Write loop
@@ -398,9 +669,16 @@ fn main() -> Result<(), eframe::Error> {
                                 break;
                             }
                         }
+                        false
                     },
                 )
                 .await;
+
+                // the same connection would only be rejected again
+                if rejected {
+                    return;
+                }
+
                 // If the race completes, it means either we lost connection or the UI closed.
                 // We pause slightly, then reconnect!
                 smol::Timer::after(Duration::from_millis(50)).await;
@@ -416,6 +694,25 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Chinese Chess",
         options,
-        Box::new(|cc| Ok(Box::new(Application::new(cc, rx_in_ui, tx_from_ui)) as Box<dyn eframe::App>)),
+        Box::new(|cc| {
+            let application = Application::new(cc, rx_in_ui, tx_from_ui);
+            let application = match review {
+                Some(pgn) => application.with_review(pgn),
+                None if watch.is_some() => application.spectating(),
+                None => application,
+            };
+            Ok(Box::new(application) as Box<dyn eframe::App>)
+        }),
     )
 }
+
+fn load_review(path: &Path, index: usize) -> Pgn {
+    let text = std::fs::read_to_string(path).expect("Failed to read PGN file");
+    let games = Pgn::parse_all(&text).unwrap_or_else(|error| panic!("Failed to parse PGN file: {error}"));
+    let count = games.len();
+
+    games
+        .into_iter()
+        .nth(index.wrapping_sub(1))
+        .unwrap_or_else(|| panic!("PGN file contains {count} games, cannot review game {index}"))
+}
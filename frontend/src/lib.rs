@@ -1,3 +1,4 @@
 pub mod arbiter;
+pub mod conformance;
 pub mod line_stream;
 pub mod protocol;
@@ -1,118 +1,320 @@
 use crate::protocol::{ArbiterMessage, PlayerMessage, Protocol};
+use log::warn;
+use smol::Timer;
 use smol::io::AsyncReadExt;
 use smol::io::AsyncWriteExt;
 use smol::net::TcpStream as AsyncTcpStream;
 use std::cell::RefCell;
-use std::io::{BufRead, Read};
-use std::io::{BufReader, Write};
+use std::io::{Error, ErrorKind, Read, Write};
 use std::net::TcpStream;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-pub struct LineStream {
-    stream: RcStream,
-    reader: RefCell<BufReader<RcStream>>,
-}
+// longest line in bytes either stream accepts, excluding the line break
+pub const MAX_LENGTH: usize = 4096;
+
+const CHUNK_LENGTH: usize = 1024;
 
-struct RcStream {
-    stream: Rc<TcpStream>,
+pub struct LineStream {
+    stream: TcpStream,
+    buffer: RefCell<LineBuffer>,
 }
 
 impl LineStream {
     pub fn new(stream: TcpStream) -> Self {
-        let stream = Rc::new(stream);
         Self {
-            stream: RcStream { stream: stream.clone() },
-            reader: RefCell::new(BufReader::new(RcStream { stream })),
+            stream,
+            buffer: RefCell::new(LineBuffer::default()),
         }
     }
 
-    pub fn read_line(&self) -> Option<String> {
-        let mut line = String::new();
+    pub fn read_line(&self) -> Result<String, Error> {
         loop {
-            if let Err(_) | Ok(0) = self.reader.borrow_mut().read_line(&mut line) {
-                return None;
+            if let Some(line) = self.buffer.borrow_mut().next_line() {
+                return line;
             }
 
-            let result = line.trim();
-            if !result.is_empty() {
-                return Some(result.to_string());
-            }
-            line.clear();
+            let mut chunk = [0u8; CHUNK_LENGTH];
+            let length = (&self.stream).read(&mut chunk).map_err(timed_out)?;
+            self.buffer.borrow_mut().append(&chunk[..length])?;
+        }
+    }
+
+    // fails with a timed out error if no line arrives in time, without losing a line that was partially read
+    pub fn read_line_within(&self, timeout: Duration) -> Result<String, Error> {
+        self.stream
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let result = self.read_line();
+        self.stream.set_read_timeout(None)?;
+        result
+    }
+
+    // whether a line can be read without blocking, also true when the connection is closed
+    pub fn poll(&self) -> bool {
+        if self.buffer.borrow().has_line() {
+            return true;
         }
+
+        if self.stream.set_nonblocking(true).is_err() {
+            return true;
+        }
+
+        let result = loop {
+            let mut chunk = [0u8; CHUNK_LENGTH];
+            let appended = match (&self.stream).read(&mut chunk) {
+                Ok(length) => self.buffer.borrow_mut().append(&chunk[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break false,
+                Err(_) => break true,
+            };
+
+            // a line that was too long is dropped, which leaves nothing to read unless another line came with it
+            if let Err(error) = appended {
+                warn!("skipping {error}");
+            }
+            if self.buffer.borrow().has_line() {
+                break true;
+            }
+        };
+
+        self.stream.set_nonblocking(false).is_err() || result
     }
 
-    pub fn write_line(&self, mut line: String) -> Result<(), std::io::Error> {
-        let mut stream: &TcpStream = &self.stream.stream;
+    pub fn write_line(&self, mut line: String) -> Result<(), Error> {
         line.push('\n');
-        stream.write(line.as_bytes()).map(|_| ())
+        (&self.stream).write_all(line.as_bytes())
     }
 
-    pub fn read(&self) -> Result<ArbiterMessage, std::io::Error> {
-        self.read_line()
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "connection closed",
-            ))
-            .and_then(|line| {
-                Protocol::decode_arbiter(&line)
-                    .ok_or(std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid message"))
-            })
+    // a line that cannot be decoded fails with an invalid data error, after which the next line can still be read
+    pub fn read(&self) -> Result<ArbiterMessage, Error> {
+        let line = self.read_line()?;
+        Protocol::decode_arbiter(&line).map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid arbiter message '{line}': {error}"),
+            )
+        })
     }
 
-    pub fn write(&self, message: &PlayerMessage) -> Result<(), std::io::Error> {
-        self.write_line(Protocol::encode_player(message))
+    // same as read, but skips lines that cannot be decoded such as messages from a newer arbiter
+    pub fn read_lenient(&self) -> Result<ArbiterMessage, Error> {
+        loop {
+            match self.read() {
+                Err(error) if error.kind() == ErrorKind::InvalidData => warn!("skipping {error}"),
+                result => return result,
+            }
+        }
     }
-}
 
-impl Read for RcStream {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.stream.as_ref().read(buf)
+    pub fn write(&self, message: &PlayerMessage) -> Result<(), Error> {
+        self.write_line(Protocol::encode_player(message))
     }
 }
 
+// clones share the connection and the bytes read from it, so any of them can continue reading where another left off
+#[derive(Clone)]
 pub struct AsyncLineStream {
     stream: AsyncTcpStream,
+    buffer: Arc<Mutex<LineBuffer>>,
 }
 
 impl AsyncLineStream {
-    pub const MAX_LENGTH: u32 = 100;
-
     pub fn new(inner: AsyncTcpStream) -> Self {
-        Self { stream: inner }
+        Self {
+            stream: inner,
+            buffer: Arc::new(Mutex::new(LineBuffer::default())),
+        }
     }
 
-    pub async fn read_line(&self) -> Option<String> {
+    // bytes are moved into the buffer as soon as they are read, so dropping the future midway loses nothing
+    pub async fn read_line(&self) -> Result<String, Error> {
         let mut inner = self.stream.clone();
-        let mut line = String::new();
-
         loop {
-            let mut buffer = [0u8; 1];
-            if let Err(_) | Ok(0) = inner.read(&mut buffer).await {
-                return None;
+            if let Some(line) = self.buffer.lock().unwrap().next_line() {
+                return line;
             }
 
-            let char = buffer[0] as char;
-
-            if char.is_ascii_whitespace() {
-                if line.is_empty() {
-                    continue;
-                }
-                if char == '\n' {
-                    while line.ends_with(' ') {
-                        line.pop();
-                    }
-                    return Some(line);
-                }
-                line.push(' ');
-            } else if char.is_ascii_graphic() {
-                line.push(char);
-            }
+            let mut chunk = [0u8; CHUNK_LENGTH];
+            let length = inner.read(&mut chunk).await?;
+            self.buffer.lock().unwrap().append(&chunk[..length])?;
         }
     }
 
-    pub async fn write_line(&self, mut line: String) -> Result<(), std::io::Error> {
+    // fails with a timed out error if no line arrives in time, without losing a line that was partially read
+    pub async fn read_line_within(&self, timeout: Duration) -> Result<String, Error> {
+        let timeout = async {
+            Timer::after(timeout).await;
+            Err(Error::new(ErrorKind::TimedOut, "timed out waiting for a line"))
+        };
+        smol::future::or(self.read_line(), timeout).await
+    }
+
+    pub async fn write_line(&self, mut line: String) -> Result<(), Error> {
         line.push('\n');
         let mut inner = self.stream.clone();
-        inner.write(line.as_bytes()).await.map(|_| ())
+        inner.write_all(line.as_bytes()).await
+    }
+
+    pub fn close(&self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+// bytes received but not yet returned as lines, shared by both streams
+#[derive(Default)]
+struct LineBuffer {
+    bytes: Vec<u8>,
+    closed: bool,
+    oversized: bool, // whether the rest of the current line is skipped as it is already too long
+}
+
+impl LineBuffer {
+    fn has_line(&self) -> bool {
+        self.closed || self.bytes.contains(&b'\n')
+    }
+
+    // an empty chunk means the connection was closed
+    fn append(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        if chunk.is_empty() {
+            self.closed = true;
+            return Ok(());
+        }
+
+        self.bytes.extend_from_slice(chunk);
+        if self.oversized {
+            self.skip_line();
+        }
+
+        // drop every line that is too long so that none can grow unboundedly, the rest of an unfinished one is skipped
+        // once it arrives
+        let mut kept = Vec::with_capacity(self.bytes.len());
+        let mut dropped = false;
+        for line in self.bytes.split_inclusive(|&byte| byte == b'\n') {
+            let complete = line.ends_with(b"\n");
+            if line.len() - complete as usize > MAX_LENGTH {
+                dropped = true;
+                self.oversized = !complete;
+            } else {
+                kept.extend_from_slice(line);
+            }
+        }
+        self.bytes = kept;
+
+        if dropped {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("line longer than {MAX_LENGTH} bytes"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn skip_line(&mut self) {
+        match self.bytes.iter().position(|&byte| byte == b'\n') {
+            Some(index) => {
+                self.bytes.drain(..=index);
+                self.oversized = false;
+            }
+            None => self.bytes.clear(),
+        }
+    }
+
+    // the next line without surrounding whitespace, skipping blank lines, or none if more bytes are needed
+    fn next_line(&mut self) -> Option<Result<String, Error>> {
+        loop {
+            let Some(index) = self.bytes.iter().position(|&byte| byte == b'\n') else {
+                let closed = Error::new(ErrorKind::UnexpectedEof, "connection closed");
+                return self.closed.then_some(Err(closed));
+            };
+
+            let bytes = self.bytes.drain(..=index).collect::<Vec<_>>();
+            let Ok(line) = String::from_utf8(bytes) else {
+                return Some(Err(Error::new(ErrorKind::InvalidData, "line is not valid utf-8")));
+            };
+
+            let line = line.trim();
+            if !line.is_empty() {
+                return Some(Ok(line.to_owned()));
+            }
+        }
+    }
+}
+
+// reads that run out of time report would block on some platforms
+fn timed_out(error: Error) -> Error {
+    match error.kind() {
+        ErrorKind::WouldBlock => Error::new(ErrorKind::TimedOut, "timed out waiting for a line"),
+        _ => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &mut LineBuffer) -> Vec<Result<String, ErrorKind>> {
+        std::iter::from_fn(|| buffer.next_line())
+            .map(|line| line.map_err(|error| error.kind()))
+            .take_while(|line| line != &Err(ErrorKind::UnexpectedEof))
+            .collect()
+    }
+
+    #[test]
+    fn splits_lines() {
+        let mut buffer = LineBuffer::default();
+        buffer.append(b"ready\r\n\n  play h2e2 \npl").unwrap();
+        assert_eq!(lines(&mut buffer), [Ok("ready".to_owned()), Ok("play h2e2".to_owned())]);
+
+        buffer.append(b"ay h0g2\n\xff\n").unwrap();
+        buffer.append(b"").unwrap();
+        assert_eq!(
+            lines(&mut buffer),
+            [Ok("play h0g2".to_owned()), Err(ErrorKind::InvalidData)]
+        );
+        assert!(buffer.next_line().unwrap().is_err());
+    }
+
+    #[test]
+    fn rejects_long_lines() {
+        let mut buffer = LineBuffer::default();
+        assert!(buffer.append(&[b'a'; MAX_LENGTH]).is_ok());
+        assert!(buffer.append(b"a").is_err());
+        assert!(buffer.append(&[b'a'; CHUNK_LENGTH]).is_ok());
+        assert!(lines(&mut buffer).is_empty());
+
+        // the stream recovers at the line after the long one
+        buffer.append(b"aaa\nready\n").unwrap();
+        assert_eq!(lines(&mut buffer), [Ok("ready".to_owned())]);
+
+        // including when the end of the long line arrives together with the next one
+        let mut long = vec![b'a'; MAX_LENGTH + 1];
+        long.extend_from_slice(b"\nready\n");
+        assert!(buffer.append(&long).is_err());
+        assert_eq!(lines(&mut buffer), [Ok("ready".to_owned())]);
+
+        // and when the long line is not the first one in the buffer
+        let mut long = b"ready\n".to_vec();
+        long.extend_from_slice(&[b'a'; MAX_LENGTH + 1]);
+        long.extend_from_slice(b"\nplay h2e2\n");
+        assert!(buffer.append(&long).is_err());
+        assert_eq!(lines(&mut buffer), [Ok("ready".to_owned()), Ok("play h2e2".to_owned())]);
+    }
+
+    #[test]
+    fn polls_past_long_lines() {
+        let listener = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stream = LineStream::new(listener.accept().unwrap().0);
+        let wait = || std::thread::sleep(Duration::from_millis(50));
+
+        // nothing can be read after a long line is dropped, so a read would block
+        peer.write_all(&[b'a'; MAX_LENGTH + 1]).unwrap();
+        peer.write_all(b"\n").unwrap();
+        wait();
+        assert!(!stream.poll());
+
+        peer.write_all(b"ready\n").unwrap();
+        wait();
+        assert!(stream.poll());
+        assert_eq!(stream.read_line().unwrap(), "ready");
     }
 }
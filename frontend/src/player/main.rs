@@ -1,12 +1,17 @@
 use chinese_chess::board::Board;
 use chinese_chess::display_format::DisplayFormat;
 use chinese_chess::game::Game;
+use chinese_chess::location::Move;
 use chinese_chess::ranker::Ranker;
 use clap::Parser;
 use frontend::line_stream::LineStream;
-use frontend::protocol::{ArbiterMessage, PlayerMessage};
+use frontend::protocol::{Analysis, ArbiterMessage, Capabilities, Capability, PROTOCOL_VERSION, PlayerMessage};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
 
 #[derive(Parser, Debug)]
 struct Arguments {
@@ -16,11 +21,78 @@ struct Arguments {
     #[arg(short, long, default_value_t = 6000)]
     port: u16,
 
+    #[arg(short, long, help = "Key to authenticate with if the arbiter requires one")]
+    key: Option<String>,
+
     #[arg(short, long, default_value = "robot")]
     name: String,
 
     #[arg(short, long, default_value_t = 4)]
     depth: u32,
+
+    #[arg(long, help = "Keep searching the expected reply while the opponent thinks")]
+    ponder: bool,
+}
+
+// searches the position after the reply the opponent is expected to play, on another thread during their turn
+struct Ponder {
+    played: Option<Move>, // our own move until the arbiter echoes it back as an update
+    expected: Move,
+    stop: Arc<AtomicBool>,
+    search: Option<JoinHandle<Ranker>>,
+}
+
+impl Ponder {
+    // the ranker is still at the position before the move it chose was played
+    fn start(ranker: &Ranker) -> Option<Self> {
+        let mut variation = ranker.variation();
+        let played = variation.next()?;
+        let expected = variation.next()?;
+
+        let mut game = ranker.game().clone();
+        game.make_move(played);
+        game.make_move(expected);
+        if game.outcome().is_some() {
+            return None;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut pondering = Ranker::new(game);
+        pondering.set_stop(Some(stop.clone()));
+
+        let search = std::thread::spawn(move || {
+            while !pondering.stopped() {
+                pondering.deeper();
+            }
+            pondering
+        });
+
+        println!("pondering on expected reply {expected}");
+        Some(Self {
+            played: Some(played),
+            expected,
+            stop,
+            search: Some(search),
+        })
+    }
+
+    // stops the search, which keeps every depth it completed
+    fn finish(mut self) -> Ranker {
+        self.stop.store(true, Ordering::Relaxed);
+        let mut ranker = self.search.take().unwrap().join().unwrap();
+        ranker.set_stop(None);
+        ranker
+    }
+}
+
+// waits for the search to give up so that it does not compete with the next one, such as after a ponder miss
+impl Drop for Ponder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(search) = self.search.take() {
+            let _ = search.join();
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -93,12 +165,230 @@ fn test() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn think(
+    ranker: &mut Ranker,
+    stream: &LineStream,
+    time: u32,
+    capabilities: Capabilities,
+    pending: &mut VecDeque<ArbiterMessage>,
+) -> Result<Option<Move>, std::io::Error> {
+    println!("{}", ranker.game().display(DisplayFormat::pretty()));
+    println!("{time}ms thinking time, from depth {}", ranker.depth());
+
+    let start = std::time::Instant::now();
+    let time = std::time::Duration::from_millis(time as u64);
+
+    // give up on a depth that runs past the time, which a head start from pondering makes more likely
+    ranker.set_deadline(Some(start + time));
+    let played = search(ranker, stream, start, time, capabilities, pending);
+    ranker.set_deadline(None);
+    played
+}
+
+fn search(
+    ranker: &mut Ranker,
+    stream: &LineStream,
+    start: std::time::Instant,
+    time: std::time::Duration,
+    capabilities: Capabilities,
+    pending: &mut VecDeque<ArbiterMessage>,
+) -> Result<Option<Move>, std::io::Error> {
+    // positions evaluated while pondering count towards the nodes but not the speed
+    let evaluated = ranker.evaluated();
+    let analysis = |ranker: &Ranker| {
+        let nodes = ranker.evaluated() as u64;
+        let seconds = start.elapsed().as_secs_f64().max(0.001);
+        Analysis {
+            depth: ranker.depth(),
+            score: ranker.score(),
+            nodes: Some(nodes),
+            nps: Some(((nodes - evaluated as u64) as f64 / seconds) as u64),
+            pv: ranker.variation().collect(),
+        }
+    };
+
+    let mut reported = false;
+    loop {
+        ranker.deeper();
+        if ranker.stopped() {
+            break;
+        }
+
+        if capabilities.contains(Capability::Info) {
+            let analysis = analysis(ranker);
+            stream.write(&PlayerMessage::Analysis { analysis })?;
+            reported = true;
+        }
+
+        // answer the arbiter between depths, deferring anything that does not concern the search
+        let mut stopped = false;
+        while stream.poll() {
+            match stream.read_lenient()? {
+                ArbiterMessage::Stop => stopped = true,
+                ArbiterMessage::Ping { token } => stream.write(&PlayerMessage::Pong { token })?,
+                message @ ArbiterMessage::Result { .. } => {
+                    // the game is already over, so there is no point in playing a move
+                    pending.push_back(message);
+                    return Ok(None);
+                }
+                message => pending.push_back(message),
+            }
+        }
+
+        if stopped {
+            println!("stopped by arbiter");
+            break;
+        }
+
+        if let Some(remain) = time.checked_sub(start.elapsed()) {
+            println!(
+                "({}) {}ms thinking time: {}",
+                ranker.depth(),
+                remain.as_millis(),
+                ranker.display(DisplayFormat::string())
+            );
+        } else {
+            break;
+        }
+    }
+
+    let duration = start.elapsed();
+    println!("{}", ranker.display(DisplayFormat::pretty()));
+    println!("total {}ms thinking time", duration.as_millis());
+
+    // the arbiter keeps the last analysis with the move, so report the depth pondering reached if none was completed
+    if !reported && capabilities.contains(Capability::Info) && ranker.best().is_some() {
+        let analysis = analysis(ranker);
+        stream.write(&PlayerMessage::Analysis { analysis })?;
+    }
+
+    let best = ranker.best();
+    match best {
+        Some(mv) => stream.write(&PlayerMessage::Play { mv })?,
+        None => println!("no viable move"),
+    }
+
+    Ok(best)
+}
+
+// the token is kept across connections so that a reconnection resumes the previous session, returns without error
+// only once the arbiter rejects the connection
+fn session(stream: &LineStream, arguments: &Arguments, token: &mut Option<String>) -> Result<(), std::io::Error> {
+    let capabilities = Capabilities::from_iter([
+        Capability::Result,
+        Capability::Info,
+        Capability::Ping,
+        Capability::Stop,
+        Capability::Illegal,
+        Capability::Resume,
+        Capability::Error,
+    ]);
+    stream.write(&PlayerMessage::Init {
+        version: PROTOCOL_VERSION,
+        capabilities,
+    })?;
+
+    if let Some(key) = arguments.key.clone() {
+        stream.write(&PlayerMessage::Auth { key })?;
+    }
+
+    let name = arguments.name.clone();
+    match token.clone() {
+        Some(token) => stream.write(&PlayerMessage::Resume { name, token })?,
+        None => stream.write(&PlayerMessage::Info { name })?,
+    }
+
+    let mut capabilities = Capabilities::none();
+    let mut ranker = Ranker::new(Game::opening());
+    let mut pending = VecDeque::new();
+    let mut ponder: Option<Ponder> = None;
+
+    loop {
+        let message = match pending.pop_front() {
+            Some(message) => message,
+            None => stream.read_lenient()?,
+        };
+
+        // the search continues through pings, a stop that came after our move and the echo of that move, anything else
+        // ends it
+        if let Some(pondering) = &mut ponder {
+            match message {
+                ArbiterMessage::Ping { .. } | ArbiterMessage::Stop => {}
+                ArbiterMessage::Update { mv } if pondering.played == Some(mv) => pondering.played = None,
+                ArbiterMessage::Update { mv } if pondering.played.is_none() && pondering.expected == mv => {
+                    let pondered = ponder.take().unwrap().finish();
+                    println!("arbiter update {mv}, ponder hit at depth {}", pondered.depth());
+                    ranker = pondered;
+                    continue;
+                }
+                ArbiterMessage::Update { .. } => {
+                    println!("ponder miss, expected {}", pondering.expected);
+                    ponder = None;
+                }
+                _ => ponder = None,
+            }
+        }
+
+        match message {
+            ArbiterMessage::Accept {
+                version,
+                capabilities: accepted,
+            } => {
+                println!("arbiter accepted protocol version {version} with capabilities [{accepted} ]");
+                capabilities = accepted;
+            }
+            ArbiterMessage::Game { fen, red_turn } => {
+                let board = Board::from_fen(&fen).unwrap();
+                ranker = Ranker::new(Game::new(board, red_turn));
+                stream.write(&PlayerMessage::Ready)?;
+            }
+            ArbiterMessage::Prompt { time } => {
+                let played = think(&mut ranker, stream, time, capabilities, &mut pending)?;
+                if arguments.ponder && played.is_some() {
+                    ponder = Ponder::start(&ranker);
+                }
+            }
+            ArbiterMessage::Update { mv } => {
+                println!("arbiter update {mv}");
+                ranker.make_move(mv);
+
+                if ranker.game().outcome().is_some() {
+                    println!("{}", ranker.game().display(DisplayFormat::pretty()));
+                }
+            }
+            ArbiterMessage::Result { conclusion } => {
+                println!("{}", ranker.game().display(DisplayFormat::pretty()));
+                println!("arbiter result {conclusion}");
+                ranker = Ranker::new(Game::opening());
+            }
+            ArbiterMessage::Ping { token } => stream.write(&PlayerMessage::Pong { token })?,
+            ArbiterMessage::Stop => {}
+            ArbiterMessage::Draw => println!("opponent offered a draw"),
+            ArbiterMessage::Illegal { mv } => println!("arbiter rejected illegal move {mv}"),
+            ArbiterMessage::Session { token: session } => *token = Some(session),
+            ArbiterMessage::Error { error } => println!("arbiter could not decode a message: {error}"),
+            ArbiterMessage::Rejected { reason } => {
+                println!("arbiter rejected the connection because {reason}");
+                return Ok(());
+            }
+            // only sent to spectators
+            ArbiterMessage::Running { .. }
+            | ArbiterMessage::Listed
+            | ArbiterMessage::Watching { .. }
+            | ArbiterMessage::Missing { .. }
+            | ArbiterMessage::Clock { .. }
+            | ArbiterMessage::Analysis { .. } => {}
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     // return test();
 
     let arguments = Arguments::parse();
 
     let address = SocketAddr::new(arguments.ip, arguments.port);
+    let mut token = None;
 
     loop {
         let stream = match TcpStream::connect(address) {
@@ -109,79 +399,10 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
         };
 
+        // lost connection, return to reconnect
         let stream = LineStream::new(stream);
-
-        if stream.write(&PlayerMessage::Init { version: 1 }).is_err() {
-            continue;
-        }
-        if stream
-            .write(&PlayerMessage::Info {
-                name: arguments.name.clone(),
-            })
-            .is_err()
-        {
-            continue;
-        }
-
-        let mut ranker = Ranker::new(Game::opening());
-
-        loop {
-            let msg = match stream.read() {
-                Ok(msg) => msg,
-                Err(_) => break, // Lost connection, break inner loop to reconnect
-            };
-
-            match msg {
-                ArbiterMessage::Game { fen, red_turn } => {
-                    let board = Board::from_fen(&fen).unwrap();
-                    ranker = Ranker::new(Game::new(board, red_turn));
-                    if stream.write(&PlayerMessage::Ready).is_err() {
-                        break;
-                    }
-                }
-                ArbiterMessage::Prompt { time } => {
-                    println!("{}", ranker.game().display(DisplayFormat::pretty()));
-                    println!("{time}ms thinking time");
-
-                    let start = std::time::Instant::now();
-                    let time = std::time::Duration::from_millis(time as u64);
-
-                    loop {
-                        ranker.deeper();
-
-                        if let Some(remain) = time.checked_sub(start.elapsed()) {
-                            println!(
-                                "({}) {}ms thinking time: {}",
-                                ranker.depth(),
-                                remain.as_millis(),
-                                ranker.display(DisplayFormat::string())
-                            );
-                        } else {
-                            break;
-                        }
-                    }
-
-                    let duration = start.elapsed();
-                    println!("{}", ranker.display(DisplayFormat::pretty()));
-                    println!("total {}ms thinking time", duration.as_millis());
-
-                    if let Some(best) = ranker.best() {
-                        if stream.write(&PlayerMessage::Play { mv: best }).is_err() {
-                            break;
-                        }
-                    } else {
-                        println!("no viable move")
-                    }
-                }
-                ArbiterMessage::Update { mv } => {
-                    println!("arbiter update {mv}");
-                    ranker.make_move(mv);
-
-                    if ranker.game().outcome().is_some() {
-                        println!("{}", ranker.game().display(DisplayFormat::pretty()));
-                    }
-                }
-            }
+        if session(&stream, &arguments, &mut token).is_ok() {
+            return Err("arbiter rejected the connection".into());
         }
     }
 }
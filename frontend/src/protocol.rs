@@ -1,16 +1,112 @@
-use chinese_chess::game::Game;
+use chinese_chess::game::{Game, Outcome};
 use chinese_chess::location::Move;
+use chinese_chess::notation;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
 pub enum ArbiterMessage {
+    Accept { version: u32, capabilities: Capabilities },
     Game { fen: String, red_turn: bool },
     Prompt { time: u32 },
     Update { mv: Move },
+    Result { conclusion: Conclusion },
+    Ping { token: u32 },
+    Stop,
+    Draw,
+    Illegal { mv: Move },
+    Running { id: u32, red: String, black: String },
+    Listed,
+    Watching { id: u32, red: String, black: String },
+    Missing { id: u32 },
+    Clock { red: u32, black: u32 },
+    Analysis { red: bool, analysis: Analysis }, // relayed from the player to move
+    Session { token: String },
+    Rejected { reason: String },
+    Error { error: ProtocolError },
 }
 
 pub enum PlayerMessage {
-    Init { version: u32 },
+    Init { version: u32, capabilities: Capabilities },
     Info { name: String },
     Ready,
     Play { mv: Move },
+    Analysis { analysis: Analysis },
+    Resign,
+    Draw { accept: bool },
+    Pong { token: u32 },
+    Spectate,
+    List,
+    Watch { id: u32 },
+    Resume { name: String, token: String },
+    Auth { key: String },
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Capability {
+    Result,
+    Info,
+    Resign,
+    Draw,
+    Ping,
+    Stop,
+    Illegal,
+    Resume,
+    Error,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct Capabilities {
+    bits: u16,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Verdict {
+    RedWon,
+    BlackWon,
+    Draw,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Reason {
+    Checkmate,
+    Stalemate,
+    MoveRule,
+    Resignation,
+    Agreement,
+    IllegalMove,
+    Timeout,
+    Disconnect,
+    WinAdjudication,
+    DrawAdjudication,
+    PlyLimit,
+    Aborted, // the game was called off and is not recorded, so its verdict means nothing
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Conclusion {
+    pub verdict: Verdict,
+    pub reason: Reason,
+}
+
+// why a line could not be decoded into a message, naming the argument and the token that is at fault
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ProtocolError {
+    Empty,
+    Unknown(String),         // the message kind is not known
+    Missing(String),         // the named argument is not there
+    Invalid(String, String), // the named argument cannot be parsed from the token
+}
+
+// progress of a search, where the node count and speed are optional as not every engine reports them
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Analysis {
+    pub depth: u32,
+    pub score: i32,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>, // nodes per second
+    pub pv: Vec<Move>,
 }
 
 impl ArbiterMessage {
@@ -21,64 +117,637 @@ impl ArbiterMessage {
     }
 }
 
+impl Capability {
+    pub const ALL: [Capability; 9] = [
+        Capability::Result,
+        Capability::Info,
+        Capability::Resign,
+        Capability::Draw,
+        Capability::Ping,
+        Capability::Stop,
+        Capability::Illegal,
+        Capability::Resume,
+        Capability::Error,
+    ];
+
+    fn bit(self) -> u16 {
+        1 << self as u8
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Capability::Result => "result",
+            Capability::Info => "info",
+            Capability::Resign => "resign",
+            Capability::Draw => "draw",
+            Capability::Ping => "ping",
+            Capability::Stop => "stop",
+            Capability::Illegal => "illegal",
+            Capability::Resume => "resume",
+            Capability::Error => "error",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Capability {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Capability::ALL
+            .into_iter()
+            .find(|capability| capability.to_string() == s)
+            .ok_or(())
+    }
+}
+
+impl Capabilities {
+    pub fn none() -> Self {
+        Self { bits: 0 }
+    }
+
+    pub fn all() -> Self {
+        Self::from_iter(Capability::ALL)
+    }
+
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.bits & capability.bit() != 0
+    }
+
+    pub fn insert(&mut self, capability: Capability) {
+        self.bits |= capability.bit();
+    }
+
+    pub fn intersect(&self, other: Self) -> Self {
+        Self {
+            bits: self.bits & other.bits,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + use<> {
+        let copy = *self;
+        Capability::ALL
+            .into_iter()
+            .filter(move |&capability| copy.contains(capability))
+    }
+}
+
+impl FromIterator<Capability> for Capabilities {
+    fn from_iter<T: IntoIterator<Item = Capability>>(iter: T) -> Self {
+        let mut result = Self::none();
+        iter.into_iter().for_each(|capability| result.insert(capability));
+        result
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for capability in self.iter() {
+            write!(f, " {capability}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Verdict {
+    pub fn winner(self) -> Option<bool> {
+        match self {
+            Verdict::RedWon => Some(true),
+            Verdict::BlackWon => Some(false),
+            Verdict::Draw => None,
+        }
+    }
+
+    pub fn from_winner(red: bool) -> Self {
+        if red { Verdict::RedWon } else { Verdict::BlackWon }
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Verdict::RedWon => "red",
+            Verdict::BlackWon => "black",
+            Verdict::Draw => "draw",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Verdict {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "red" => Ok(Verdict::RedWon),
+            "black" => Ok(Verdict::BlackWon),
+            "draw" => Ok(Verdict::Draw),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Reason {
+    pub const ALL: [Reason; 12] = [
+        Reason::Checkmate,
+        Reason::Stalemate,
+        Reason::MoveRule,
+        Reason::Resignation,
+        Reason::Agreement,
+        Reason::IllegalMove,
+        Reason::Timeout,
+        Reason::Disconnect,
+        Reason::WinAdjudication,
+        Reason::DrawAdjudication,
+        Reason::PlyLimit,
+        Reason::Aborted,
+    ];
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Reason::Checkmate => "checkmate",
+            Reason::Stalemate => "stalemate",
+            Reason::MoveRule => "move_rule",
+            Reason::Resignation => "resignation",
+            Reason::Agreement => "agreement",
+            Reason::IllegalMove => "illegal_move",
+            Reason::Timeout => "timeout",
+            Reason::Disconnect => "disconnect",
+            Reason::WinAdjudication => "win_adjudication",
+            Reason::DrawAdjudication => "draw_adjudication",
+            Reason::PlyLimit => "ply_limit",
+            Reason::Aborted => "aborted",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for Reason {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Reason::ALL.into_iter().find(|reason| reason.to_string() == s).ok_or(())
+    }
+}
+
+impl Conclusion {
+    pub fn new(verdict: Verdict, reason: Reason) -> Self {
+        Self { verdict, reason }
+    }
+
+    // the opponent of the player with the given color wins
+    pub fn forfeit(red: bool, reason: Reason) -> Self {
+        Self::new(Verdict::from_winner(!red), reason)
+    }
+}
+
+impl From<Outcome> for Conclusion {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::RedWon => Self::new(Verdict::RedWon, Reason::Checkmate),
+            Outcome::BlackWon => Self::new(Verdict::BlackWon, Reason::Checkmate),
+            Outcome::Stalemate => Self::new(Verdict::Draw, Reason::Stalemate),
+            Outcome::MoveRule => Self::new(Verdict::Draw, Reason::MoveRule),
+        }
+    }
+}
+
+impl Display for Conclusion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.verdict {
+            Verdict::RedWon => write!(f, "red won by {}", self.reason),
+            Verdict::BlackWon => write!(f, "black won by {}", self.reason),
+            Verdict::Draw => write!(f, "draw by {}", self.reason),
+        }
+    }
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Empty => write!(f, "empty message"),
+            ProtocolError::Unknown(kind) => write!(f, "unknown message '{kind}'"),
+            ProtocolError::Missing(argument) => write!(f, "missing {argument}"),
+            ProtocolError::Invalid(argument, token) => write!(f, "invalid {argument} '{token}'"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+// the arguments of an info message, without the message kind
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "depth {} score {}", self.depth, self.score)?;
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {nodes}")?;
+        }
+        if let Some(nps) = self.nps {
+            write!(f, " nps {nps}")?;
+        }
+        if !self.pv.is_empty() {
+            write!(f, " pv")?;
+            self.pv.iter().try_for_each(|mv| write!(f, " {mv}"))?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Protocol;
 
 impl Protocol {
-    fn decode(line: &str) -> (&str, impl Iterator<Item = &str>) {
+    fn decode(line: &str) -> Result<(&str, impl Iterator<Item = &str>), ProtocolError> {
         let mut parts = line.split_whitespace().fuse();
-        (parts.next().unwrap_or(""), parts)
+        let kind = parts.next().ok_or(ProtocolError::Empty)?;
+        Ok((kind, parts))
+    }
+
+    fn next<'a>(arguments: &mut impl Iterator<Item = &'a str>, argument: &str) -> Result<&'a str, ProtocolError> {
+        arguments
+            .next()
+            .ok_or_else(|| ProtocolError::Missing(argument.to_string()))
     }
 
-    pub fn decode_arbiter(line: &str) -> Option<ArbiterMessage> {
-        let (kind, mut arguments) = Protocol::decode(line);
+    fn parse<'a, T: FromStr>(
+        arguments: &mut impl Iterator<Item = &'a str>,
+        argument: &str,
+    ) -> Result<T, ProtocolError> {
+        let token = Protocol::next(arguments, argument)?;
+        token
+            .parse()
+            .map_err(|_| ProtocolError::Invalid(argument.to_string(), token.to_string()))
+    }
+
+    fn decode_capabilities<'a>(arguments: impl Iterator<Item = &'a str>) -> Capabilities {
+        // unknown capabilities are ignored so newer peers can advertise more than we understand
+        arguments.filter_map(|argument| argument.parse().ok()).collect()
+    }
+
+    fn decode_move(token: &str) -> Result<Move, ProtocolError> {
+        notation::parse_iccs(token).map_err(|_| ProtocolError::Invalid("move".to_string(), token.to_string()))
+    }
+
+    fn next_move<'a>(arguments: &mut impl Iterator<Item = &'a str>) -> Result<Move, ProtocolError> {
+        Protocol::decode_move(Protocol::next(arguments, "move")?)
+    }
+
+    pub fn decode_analysis<'a>(mut arguments: impl Iterator<Item = &'a str>) -> Result<Analysis, ProtocolError> {
+        let mut depth = None;
+        let mut score = None;
+        let mut nodes = None;
+        let mut nps = None;
+        let mut pv = Vec::new();
+
+        while let Some(key) = arguments.next() {
+            match key {
+                "depth" => depth = Some(Protocol::parse(&mut arguments, "depth")?),
+                "score" => score = Some(Protocol::parse(&mut arguments, "score")?),
+                "nodes" => nodes = Some(Protocol::parse(&mut arguments, "nodes")?),
+                "nps" => nps = Some(Protocol::parse(&mut arguments, "nps")?),
+                "pv" => {
+                    // the principal variation consumes the rest of the line
+                    for mv in arguments.by_ref() {
+                        pv.push(Protocol::decode_move(mv)?);
+                    }
+                }
+                _ => return Err(ProtocolError::Invalid("info".to_string(), key.to_string())),
+            }
+        }
+
+        Ok(Analysis {
+            depth: depth.ok_or_else(|| ProtocolError::Missing("depth".to_string()))?,
+            score: score.ok_or_else(|| ProtocolError::Missing("score".to_string()))?,
+            nodes,
+            nps,
+            pv,
+        })
+    }
+
+    fn decode_error<'a>(mut arguments: impl Iterator<Item = &'a str>) -> Result<ProtocolError, ProtocolError> {
+        let arguments = &mut arguments;
+        let error = match Protocol::next(arguments, "error")? {
+            "empty" => ProtocolError::Empty,
+            "unknown" => ProtocolError::Unknown(Protocol::next(arguments, "message")?.to_string()),
+            "missing" => ProtocolError::Missing(Protocol::next(arguments, "argument")?.to_string()),
+            "invalid" => ProtocolError::Invalid(
+                Protocol::next(arguments, "argument")?.to_string(),
+                Protocol::next(arguments, "token")?.to_string(),
+            ),
+            kind => return Err(ProtocolError::Invalid("error".to_string(), kind.to_string())),
+        };
+        Ok(error)
+    }
+
+    pub fn decode_arbiter(line: &str) -> Result<ArbiterMessage, ProtocolError> {
+        let (kind, mut arguments) = Protocol::decode(line)?;
+        let arguments = &mut arguments;
         let message = match kind {
+            "accept" => ArbiterMessage::Accept {
+                version: Protocol::parse(arguments, "version")?,
+                capabilities: Protocol::decode_capabilities(arguments),
+            },
             "game" => ArbiterMessage::Game {
-                fen: arguments.next()?.to_string(),
-                red_turn: arguments.next()?.parse().ok()?,
+                fen: Protocol::next(arguments, "fen")?.to_string(),
+                red_turn: Protocol::parse(arguments, "red_turn")?,
             },
             "prompt" => ArbiterMessage::Prompt {
-                time: arguments.next()?.parse().ok()?,
+                time: Protocol::parse(arguments, "time")?,
             },
             "update" => ArbiterMessage::Update {
-                mv: arguments.next()?.parse().ok()?,
+                mv: Protocol::next_move(arguments)?,
+            },
+            "result" => ArbiterMessage::Result {
+                conclusion: Conclusion::new(
+                    Protocol::parse(arguments, "verdict")?,
+                    Protocol::parse(arguments, "reason")?,
+                ),
+            },
+            "ping" => ArbiterMessage::Ping {
+                token: Protocol::parse(arguments, "token")?,
+            },
+            "stop" => ArbiterMessage::Stop,
+            "draw" => match Protocol::next(arguments, "draw")? {
+                "offer" => ArbiterMessage::Draw,
+                token => return Err(ProtocolError::Invalid("draw".to_string(), token.to_string())),
             },
-            _ => return None,
+            "illegal" => ArbiterMessage::Illegal {
+                mv: Protocol::next_move(arguments)?,
+            },
+            "running" => ArbiterMessage::Running {
+                id: Protocol::parse(arguments, "id")?,
+                red: Protocol::next(arguments, "name")?.to_string(),
+                black: Protocol::next(arguments, "name")?.to_string(),
+            },
+            "listed" => ArbiterMessage::Listed,
+            "watching" => ArbiterMessage::Watching {
+                id: Protocol::parse(arguments, "id")?,
+                red: Protocol::next(arguments, "name")?.to_string(),
+                black: Protocol::next(arguments, "name")?.to_string(),
+            },
+            "missing" => ArbiterMessage::Missing {
+                id: Protocol::parse(arguments, "id")?,
+            },
+            "clock" => ArbiterMessage::Clock {
+                red: Protocol::parse(arguments, "used")?,
+                black: Protocol::parse(arguments, "used")?,
+            },
+            "analysis" => ArbiterMessage::Analysis {
+                red: match Protocol::next(arguments, "side")? {
+                    "red" => true,
+                    "black" => false,
+                    token => return Err(ProtocolError::Invalid("side".to_string(), token.to_string())),
+                },
+                analysis: Protocol::decode_analysis(arguments)?,
+            },
+            "session" => ArbiterMessage::Session {
+                token: Protocol::next(arguments, "token")?.to_string(),
+            },
+            // the reason is free text for people to read, so it consumes the rest of the line
+            "rejected" => ArbiterMessage::Rejected {
+                reason: arguments.collect::<Vec<_>>().join(" "),
+            },
+            "error" => ArbiterMessage::Error {
+                error: Protocol::decode_error(arguments)?,
+            },
+            _ => return Err(ProtocolError::Unknown(kind.to_string())),
         };
-        Some(message)
+        Ok(message)
+    }
+
+    // same as decode_player, except that info is the name of the player rather than search analysis
+    pub fn decode_handshake(line: &str) -> Result<PlayerMessage, ProtocolError> {
+        let (kind, mut arguments) = Protocol::decode(line)?;
+        match kind {
+            "info" => Ok(PlayerMessage::Info {
+                name: Protocol::next(&mut arguments, "name")?.to_string(),
+            }),
+            _ => Protocol::decode_player(line),
+        }
     }
 
-    pub fn decode_player(line: &str) -> Option<PlayerMessage> {
-        let (kind, mut arguments) = Protocol::decode(line);
+    pub fn decode_player(line: &str) -> Result<PlayerMessage, ProtocolError> {
+        let (kind, mut arguments) = Protocol::decode(line)?;
+        let arguments = &mut arguments;
         let message = match kind {
             "init" => PlayerMessage::Init {
-                version: arguments.next()?.parse().ok()?,
+                version: Protocol::parse(arguments, "version")?,
+                capabilities: Protocol::decode_capabilities(arguments),
             },
-            "info" => PlayerMessage::Info {
-                name: arguments.next()?.to_string(),
+            "info" => PlayerMessage::Analysis {
+                analysis: Protocol::decode_analysis(arguments)?,
             },
             "ready" => PlayerMessage::Ready,
             "play" => PlayerMessage::Play {
-                mv: arguments.next()?.parse().ok()?,
+                mv: Protocol::next_move(arguments)?,
+            },
+            "resign" => PlayerMessage::Resign,
+            "draw" => PlayerMessage::Draw {
+                accept: match Protocol::next(arguments, "draw")? {
+                    "offer" => false,
+                    "accept" => true,
+                    token => return Err(ProtocolError::Invalid("draw".to_string(), token.to_string())),
+                },
+            },
+            "pong" => PlayerMessage::Pong {
+                token: Protocol::parse(arguments, "token")?,
+            },
+            "spectate" => PlayerMessage::Spectate,
+            "list" => PlayerMessage::List,
+            "watch" => PlayerMessage::Watch {
+                id: Protocol::parse(arguments, "id")?,
             },
-            _ => return None,
+            "resume" => PlayerMessage::Resume {
+                name: Protocol::next(arguments, "name")?.to_string(),
+                token: Protocol::next(arguments, "session")?.to_string(),
+            },
+            "auth" => PlayerMessage::Auth {
+                key: Protocol::next(arguments, "key")?.to_string(),
+            },
+            _ => return Err(ProtocolError::Unknown(kind.to_string())),
         };
-        Some(message)
+        Ok(message)
     }
 
     pub fn encode_arbiter(message: &ArbiterMessage) -> String {
         match message {
+            ArbiterMessage::Accept { version, capabilities } => format!("accept {version}{capabilities}"),
             ArbiterMessage::Game { fen, red_turn } => format!("game {fen} {red_turn}"),
             ArbiterMessage::Prompt { time } => format!("prompt {time}"),
             ArbiterMessage::Update { mv } => format!("update {mv}"),
+            ArbiterMessage::Result { conclusion } => format!("result {} {}", conclusion.verdict, conclusion.reason),
+            ArbiterMessage::Ping { token } => format!("ping {token}"),
+            ArbiterMessage::Stop => "stop".to_string(),
+            ArbiterMessage::Draw => "draw offer".to_string(),
+            ArbiterMessage::Illegal { mv } => format!("illegal {mv}"),
+            ArbiterMessage::Running { id, red, black } => format!("running {id} {red} {black}"),
+            ArbiterMessage::Listed => "listed".to_string(),
+            ArbiterMessage::Watching { id, red, black } => format!("watching {id} {red} {black}"),
+            ArbiterMessage::Missing { id } => format!("missing {id}"),
+            ArbiterMessage::Clock { red, black } => format!("clock {red} {black}"),
+            ArbiterMessage::Analysis { red, analysis } => {
+                format!("analysis {} {analysis}", if *red { "red" } else { "black" })
+            }
+            ArbiterMessage::Session { token } => format!("session {token}"),
+            ArbiterMessage::Rejected { reason } => format!("rejected {reason}"),
+            ArbiterMessage::Error { error } => match error {
+                ProtocolError::Empty => "error empty".to_string(),
+                ProtocolError::Unknown(kind) => format!("error unknown {kind}"),
+                ProtocolError::Missing(argument) => format!("error missing {argument}"),
+                ProtocolError::Invalid(argument, token) => format!("error invalid {argument} {token}"),
+            },
         }
     }
 
     pub fn encode_player(message: &PlayerMessage) -> String {
         match message {
-            PlayerMessage::Init { version } => format!("init {version}"),
+            PlayerMessage::Init { version, capabilities } => format!("init {version}{capabilities}"),
             PlayerMessage::Info { name } => format!("info {name}"),
             PlayerMessage::Ready => "ready".to_string(),
             PlayerMessage::Play { mv } => format!("play {mv}"),
+            PlayerMessage::Analysis { analysis } => format!("info {analysis}"),
+            PlayerMessage::Resign => "resign".to_string(),
+            PlayerMessage::Draw { accept: false } => "draw offer".to_string(),
+            PlayerMessage::Draw { accept: true } => "draw accept".to_string(),
+            PlayerMessage::Pong { token } => format!("pong {token}"),
+            PlayerMessage::Spectate => "spectate".to_string(),
+            PlayerMessage::List => "list".to_string(),
+            PlayerMessage::Watch { id } => format!("watch {id}"),
+            PlayerMessage::Resume { name, token } => format!("resume {name} {token}"),
+            PlayerMessage::Auth { key } => format!("auth {key}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_errors() {
+        let error = |line: &str| Protocol::decode_player(line).err();
+        assert_eq!(error(""), Some(ProtocolError::Empty));
+        assert_eq!(error("castle"), Some(ProtocolError::Unknown("castle".to_owned())));
+        assert_eq!(error("play"), Some(ProtocolError::Missing("move".to_owned())));
+        assert_eq!(
+            error("play h2x2"),
+            Some(ProtocolError::Invalid("move".to_owned(), "h2x2".to_owned()))
+        );
+        assert_eq!(
+            error("info depth 3 score high"),
+            Some(ProtocolError::Invalid("score".to_owned(), "high".to_owned()))
+        );
+        assert_eq!(
+            error("info depth 3 pv h2e2"),
+            Some(ProtocolError::Missing("score".to_owned()))
+        );
+        assert_eq!(error("play h2e2"), None);
+
+        // a short info is only the name of the player during the handshake
+        assert_eq!(error("info depth"), Some(ProtocolError::Missing("depth".to_owned())));
+        assert!(matches!(
+            Protocol::decode_handshake("info depth"),
+            Ok(PlayerMessage::Info { name }) if name == "depth"
+        ));
+
+        // errors survive being sent back to the peer
+        let invalid = ProtocolError::Invalid("move".to_owned(), "h2x2".to_owned());
+        let line = Protocol::encode_arbiter(&ArbiterMessage::Error { error: invalid.clone() });
+        assert_eq!(line, "error invalid move h2x2");
+        assert!(matches!(Protocol::decode_arbiter(&line), Ok(ArbiterMessage::Error { error }) if error == invalid));
+    }
+
+    #[test]
+    fn analysis() {
+        let line = "info depth 7 score -35 nodes 120000 nps 480000 pv h2e2 h9g7";
+        let Ok(PlayerMessage::Analysis { analysis }) = Protocol::decode_player(line) else {
+            panic!("failed to decode '{line}'");
+        };
+        assert_eq!(analysis.nodes, Some(120000));
+        assert_eq!(analysis.pv.len(), 2);
+        let message = PlayerMessage::Analysis {
+            analysis: analysis.clone(),
+        };
+        assert_eq!(Protocol::encode_player(&message), line);
+
+        // older players only report depth and score
+        let short = Protocol::decode_analysis("depth 2 score 10".split(' ')).unwrap();
+        assert_eq!((short.nodes, short.nps), (None, None));
+
+        let relayed = Protocol::encode_arbiter(&ArbiterMessage::Analysis {
+            red: false,
+            analysis: analysis.clone(),
+        });
+        assert_eq!(
+            relayed,
+            "analysis black depth 7 score -35 nodes 120000 nps 480000 pv h2e2 h9g7"
+        );
+        assert!(matches!(
+            Protocol::decode_arbiter(&relayed),
+            Ok(ArbiterMessage::Analysis { red: false, analysis: decoded }) if decoded == analysis
+        ));
+    }
+
+    #[test]
+    fn capabilities() {
+        let capabilities = Capabilities::from_iter([Capability::Stop, Capability::Info, Capability::Stop]);
+        assert!(capabilities.contains(Capability::Info));
+        assert!(!capabilities.contains(Capability::Resume));
+        assert_eq!(
+            capabilities.iter().collect::<Vec<_>>(),
+            [Capability::Info, Capability::Stop]
+        );
+        assert_eq!(capabilities.to_string(), " info stop");
+
+        let other = Capabilities::from_iter([Capability::Stop, Capability::Ping]);
+        assert_eq!(
+            capabilities.intersect(other),
+            Capabilities::from_iter([Capability::Stop])
+        );
+        assert_eq!(capabilities.intersect(Capabilities::none()), Capabilities::none());
+        assert_eq!(Capabilities::all().intersect(capabilities), capabilities);
+
+        for capability in Capability::ALL {
+            assert_eq!(capability.to_string().parse(), Ok(capability));
         }
+        assert_eq!("castle".parse::<Capability>(), Err(()));
+    }
+
+    #[test]
+    fn handshake() {
+        let capabilities = Capabilities::from_iter([Capability::Result, Capability::Resume, Capability::Error]);
+
+        let init = Protocol::encode_player(&PlayerMessage::Init {
+            version: PROTOCOL_VERSION,
+            capabilities,
+        });
+        assert_eq!(init, "init 2 result resume error");
+        assert!(matches!(
+            Protocol::decode_player(&init),
+            Ok(PlayerMessage::Init { version: PROTOCOL_VERSION, capabilities: decoded }) if decoded == capabilities
+        ));
+
+        let accept = Protocol::encode_arbiter(&ArbiterMessage::Accept {
+            version: PROTOCOL_VERSION,
+            capabilities: Capabilities::none(),
+        });
+        assert_eq!(accept, "accept 2");
+        assert!(matches!(
+            Protocol::decode_arbiter(&accept),
+            Ok(ArbiterMessage::Accept { version: PROTOCOL_VERSION, capabilities }) if capabilities == Capabilities::none()
+        ));
+
+        // capabilities from newer peers are ignored, while the version must be a number
+        assert!(matches!(
+            Protocol::decode_player("init 3 stop teleport"),
+            Ok(PlayerMessage::Init { version: 3, capabilities }) if capabilities == Capabilities::from_iter([Capability::Stop])
+        ));
+        assert_eq!(
+            Protocol::decode_arbiter("accept two").err(),
+            Some(ProtocolError::Invalid("version".to_owned(), "two".to_owned()))
+        );
     }
 }
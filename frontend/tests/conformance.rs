@@ -0,0 +1,29 @@
+use frontend::conformance;
+use std::process::{Command, Stdio};
+
+#[test]
+fn player() {
+    check(conformance::run(|port| {
+        Command::new(env!("CARGO_BIN_EXE_player"))
+            .args(["--port", &port.to_string()])
+            .stdout(Stdio::null())
+            .spawn()
+    }));
+}
+
+// the bridge driving the ucci engine of this crate, so both ends of the bridge are covered
+#[test]
+fn bridge() {
+    check(conformance::run(|port| {
+        Command::new(env!("CARGO_BIN_EXE_bridge"))
+            .args(["--port", &port.to_string(), env!("CARGO_BIN_EXE_engine")])
+            .stdout(Stdio::null())
+            .spawn()
+    }));
+}
+
+fn check(results: Vec<(&'static str, Result<bool, String>)>) {
+    for (name, result) in results {
+        assert!(result.is_ok(), "{name} failed: {}", result.unwrap_err());
+    }
+}
@@ -180,8 +180,7 @@ impl Board {
                     add(from.shift_y(-1));
 
                     let mut current = from.normalize(red);
-                    loop {
-                        let Some(to) = current.shift_y(1) else { break };
+                    while let Some(to) = current.shift_y(1) {
                         current = to;
 
                         let to = to.normalize(red);
@@ -343,6 +342,12 @@ impl Board {
     }
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Index<Location> for Board {
     type Output = Option<Piece>;
     fn index(&self, index: Location) -> &Self::Output {
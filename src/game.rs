@@ -1,6 +1,7 @@
 use crate::board::Board;
 use crate::display_format::{AnsiEffects, DisplayFormat};
 use crate::location::{Location, Move};
+use crate::notation;
 use crate::piece::{Piece, PieceKind};
 use std::fmt::{Display, Formatter};
 use std::ops::Index;
@@ -211,9 +212,11 @@ impl Game {
                 self.format_captured(f, Board::HEIGHT as usize)?;
                 writeln!(f)?;
 
-                if let Some(mv) = game.history.last().map(|&(mv, _)| mv) {
-                    let piece = game.board[mv.to].unwrap().display(format.with_concise(true));
-                    write!(f, "({}) {} {piece} - ", game.history.len(), mv)?;
+                if let Some(&(mv, capture)) = game.history.last() {
+                    let mut board = game.board.clone();
+                    board.undo_move(mv, capture);
+                    let notation = notation::display(&board, mv, format);
+                    write!(f, "({}) {} {notation} - ", game.history.len(), mv)?;
                 }
 
                 if let Some(outcome) = game.outcome() {
@@ -2,5 +2,7 @@ pub mod board;
 pub mod display_format;
 pub mod game;
 pub mod location;
+pub mod notation;
+pub mod pgn;
 pub mod piece;
 pub mod ranker;
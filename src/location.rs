@@ -34,7 +34,7 @@ impl Location {
 
     pub fn shift_x(&self, y: i8) -> Option<Self> {
         let new_x = self.x + y;
-        if 0 > new_x || new_x >= Board::WIDTH {
+        if !(0..Board::WIDTH).contains(&new_x) {
             return None;
         }
         Some(Self { x: new_x, y: self.y })
@@ -42,7 +42,7 @@ impl Location {
 
     pub fn shift_y(&self, x: i8) -> Option<Self> {
         let new_y = self.y + x;
-        if 0 > new_y || new_y >= Board::HEIGHT {
+        if !(0..Board::HEIGHT).contains(&new_y) {
             return None;
         }
         Some(Self { x: self.x, y: new_y })
@@ -76,6 +76,12 @@ impl Location {
     }
 }
 
+impl Default for Location {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for Location {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}{}", (b'a' + self.x as u8) as char, self.y)
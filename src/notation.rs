@@ -0,0 +1,361 @@
+use crate::board::Board;
+use crate::display_format::{AnsiEffects, DisplayFormat};
+use crate::game::Game;
+use crate::location::{Location, Move};
+use crate::piece::{Piece, PieceKind};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum NotationError {
+    Malformed(String),  // text that does not describe a move at all
+    Empty(Location),    // no piece to move at the origin
+    Opponent(Location), // the piece at the origin belongs to the player not to move
+    Illegal(Move),      // the piece cannot move there, or the move leaves the own king in check
+    Concluded(Move),    // the game is already over
+}
+
+// a move described relative to the player making it, as both wxf (C2.5) and traditional chinese (炮二平五) write it
+struct Description {
+    piece: Piece,
+    origin: Origin,
+    direction: char, // one of + - . for forward, backward and traverse
+    target: i8,      // destination file or number of ranks moved, from 1 to 9
+}
+
+enum Origin {
+    File(i8),
+    Tandem { index: usize, count: usize }, // position from the front among identical pieces on the same file
+}
+
+impl Description {
+    fn new(board: &Board, mv: Move) -> Self {
+        let piece = board[mv.from].unwrap();
+        let red = piece.is_red();
+
+        // files are numbered from 1 to 9 starting at the right of the player, and forward is towards the opponent
+        let file = |location: Location| {
+            if red {
+                Board::WIDTH - location.x()
+            } else {
+                location.x() + 1
+            }
+        };
+        let forward = mv.to.normalize(red).y() - mv.from.normalize(red).y();
+
+        let direction = match forward {
+            1.. => '+',
+            ..0 => '-',
+            0 => '.',
+        };
+
+        let target = match piece.kind() {
+            PieceKind::Advisor | PieceKind::Elephant | PieceKind::Horse => file(mv.to),
+            _ if forward == 0 => file(mv.to),
+            _ => forward.abs(),
+        };
+
+        // advisors and elephants are never ambiguous as their direction tells them apart
+        let mut tandem = (0..Board::HEIGHT)
+            .map(|y| Location::from_xy(mv.from.x(), y).unwrap())
+            .filter(|&location| board[location] == Some(piece))
+            .collect::<Vec<_>>();
+
+        let origin = match piece.kind() {
+            PieceKind::Horse | PieceKind::Chariot | PieceKind::Cannon | PieceKind::Pawn if tandem.len() > 1 => {
+                tandem.sort_by_key(|location| -location.normalize(red).y());
+                Origin::Tandem {
+                    index: tandem.iter().position(|&location| location == mv.from).unwrap(),
+                    count: tandem.len(),
+                }
+            }
+            _ => Origin::File(file(mv.from)),
+        };
+
+        Self {
+            piece,
+            origin,
+            direction,
+            target,
+        }
+    }
+
+    fn wxf(&self) -> String {
+        let letter = match self.piece.kind() {
+            PieceKind::King => 'K',
+            PieceKind::Advisor => 'A',
+            PieceKind::Elephant => 'E',
+            PieceKind::Horse => 'H',
+            PieceKind::Chariot => 'R',
+            PieceKind::Cannon => 'C',
+            PieceKind::Pawn => 'P',
+        };
+
+        let prefix = match self.origin {
+            Origin::File(file) => format!("{letter}{file}"),
+            Origin::Tandem { index: 0, count: 2 } => format!("{letter}+"),
+            Origin::Tandem { index: _, count: 2 } => format!("{letter}-"),
+            Origin::Tandem { index, .. } => format!("{}{letter}", index + 1),
+        };
+
+        format!("{prefix}{}{}", self.direction, self.target)
+    }
+
+    fn chinese(&self) -> String {
+        let red = self.piece.is_red();
+        let number = |value: i8| {
+            let numbers = if red {
+                "一二三四五六七八九"
+            } else {
+                "１２３４５６７８９"
+            };
+            numbers.chars().nth(value as usize - 1).unwrap()
+        };
+
+        let piece = self.piece.display(DisplayFormat {
+            chinese: true,
+            effects: false,
+            concise: true,
+        });
+
+        let prefix = match self.origin {
+            Origin::File(file) => format!("{piece}{}", number(file)),
+            Origin::Tandem { index, count } => {
+                let position = match (index, count) {
+                    (0, _) => '前',
+                    (1, 3) => '中',
+                    (_, 2 | 3) => '後',
+                    _ => "一二三四五".chars().nth(index).unwrap(),
+                };
+                format!("{position}{piece}")
+            }
+        };
+
+        let direction = match self.direction {
+            '+' => '進',
+            '-' => '退',
+            _ => '平',
+        };
+
+        format!("{prefix}{direction}{}", number(self.target))
+    }
+}
+
+// iccs coordinates with files a to i and ranks 0 to 9 from the red side, such as h2-e2
+pub fn iccs(mv: Move) -> String {
+    format!("{}-{}", mv.from, mv.to)
+}
+
+// accepts both h2-e2 and the h2e2 form used by ucci, in either case
+pub fn parse_iccs(text: &str) -> Result<Move, NotationError> {
+    let malformed = || NotationError::Malformed(text.to_owned());
+    let (from, to) = match text.split_once('-') {
+        Some(parts) => parts,
+        None => text.split_at_checked(2).ok_or_else(malformed)?,
+    };
+
+    if from.len() != 2 || to.len() != 2 {
+        return Err(malformed());
+    }
+
+    Ok(Move {
+        from: from.parse().map_err(|_| malformed())?,
+        to: to.parse().map_err(|_| malformed())?,
+    })
+}
+
+// checks that a well formed move can be played in the game, telling why not otherwise
+pub fn validate(game: &Game, mv: Move) -> Result<Move, NotationError> {
+    match game[mv.from] {
+        None => return Err(NotationError::Empty(mv.from)),
+        Some(piece) if piece.is_red() != game.red_turn() => return Err(NotationError::Opponent(mv.from)),
+        Some(_) => {}
+    }
+
+    if game.outcome().is_some() {
+        return Err(NotationError::Concluded(mv));
+    }
+
+    if !game.iter_moves().any(|legal| legal == mv) {
+        return Err(NotationError::Illegal(mv));
+    }
+
+    Ok(mv)
+}
+
+// a full fen as other xiangqi software writes it, with horses and elephants as knights and bishops
+pub fn parse_fen(fen: &str) -> Option<Game> {
+    let mut parts = fen.split_whitespace();
+    let board = parts.next()?.chars().map(|current| match current {
+        'n' => 'h',
+        'N' => 'H',
+        'b' => 'e',
+        'B' => 'E',
+        _ => current,
+    });
+
+    let red_turn = match parts.next() {
+        None | Some("w" | "r") => true,
+        Some("b") => false,
+        Some(_) => return None,
+    };
+
+    Some(Game::new(Board::from_fen(&board.collect::<String>())?, red_turn))
+}
+
+pub fn fen(game: &Game) -> String {
+    let fen = game.board().fen();
+    let board = fen.chars().map(|current| match current {
+        'h' => 'n',
+        'H' => 'N',
+        'e' => 'b',
+        'E' => 'B',
+        _ => current,
+    });
+
+    let side = if game.red_turn() { 'w' } else { 'b' };
+    format!("{} {side} - - 0 1", board.collect::<String>())
+}
+
+pub fn wxf(board: &Board, mv: Move) -> String {
+    Description::new(board, mv).wxf()
+}
+
+pub fn chinese(board: &Board, mv: Move) -> String {
+    Description::new(board, mv).chinese()
+}
+
+// describes a move that is about to be made on the board in the notation chosen by the format
+pub fn display(board: &Board, mv: Move, format: DisplayFormat) -> impl Display + use<> {
+    let description = Description::new(board, mv);
+    let text = if format.chinese {
+        description.chinese()
+    } else {
+        description.wxf()
+    };
+
+    if !format.effects {
+        return text;
+    }
+
+    let effect = if description.piece.is_red() {
+        AnsiEffects::RED
+    } else {
+        AnsiEffects::BOLD
+    };
+    format!("{effect}{text}{}", AnsiEffects::CLEAR)
+}
+
+// finds the legal move written in either wxf or chinese notation, none if there is no such move or it is ambiguous
+pub fn parse(board: &Board, red: bool, text: &str) -> Option<Move> {
+    let text = normalize(text);
+    let mut matches = board.iter_legal_moves(red).filter(|&mv| {
+        let description = Description::new(board, mv);
+        normalize(&description.wxf()) == text || normalize(&description.chinese()) == text
+    });
+
+    let mv = matches.next()?;
+    matches.next().is_none().then_some(mv)
+}
+
+// maps the different ways of writing the same move onto one spelling, including simplified characters
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|current| !current.is_whitespace())
+        .map(|current| match current {
+            '帥' | '帅' | '將' | '将' | 'k' => 'K',
+            '仕' | '士' | 'a' => 'A',
+            '相' | '象' | 'e' | 'B' | 'b' => 'E',
+            '傌' | '馬' | '马' | 'h' | 'N' | 'n' => 'H',
+            '俥' | '車' | '车' | 'r' => 'R',
+            '炮' | '砲' | '包' | 'c' => 'C',
+            '兵' | '卒' | 'p' => 'P',
+            '進' | '进' | '前' => '+',
+            '退' | '後' | '后' => '-',
+            '平' | '=' => '.',
+            '中' => '2',
+            '１'..='９' => char::from_digit(current as u32 - '０' as u32, 10).unwrap(),
+            _ => match "一二三四五六七八九".chars().position(|number| number == current) {
+                Some(index) => char::from_digit(index as u32 + 1, 10).unwrap(),
+                None => current,
+            },
+        })
+        .collect()
+}
+
+impl Display for NotationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotationError::Malformed(text) => write!(f, "'{text}' is not a move"),
+            NotationError::Empty(location) => write!(f, "there is no piece at {location}"),
+            NotationError::Opponent(location) => write!(f, "the piece at {location} belongs to the opponent"),
+            NotationError::Illegal(mv) => write!(f, "{mv} is not a legal move"),
+            NotationError::Concluded(mv) => write!(f, "{mv} cannot be played as the game is over"),
+        }
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Board::opening(), "h2e2", "C2.5", "炮二平五")]
+    #[case(Board::opening(), "b0c2", "H8+7", "傌八進七")]
+    #[case(Board::opening(), "h9g7", "H8+7", "馬８進７")]
+    #[case(Board::opening(), "a0a1", "R9+1", "俥九進一")]
+    #[case(Board::opening(), "f0e1", "A4+5", "仕四進五")]
+    #[case(Board::opening(), "g9e7", "E7+5", "象７進５")]
+    #[case(Board::opening(), "c3c4", "P7+1", "兵七進一")]
+    #[case(Board::from_fen("4k4/9/9/9/9/9/9/4C4/9/3KC4").unwrap(), "e2e5", "C++3", "前炮進三")]
+    #[case(Board::from_fen("4k4/9/9/9/9/9/9/4C4/9/3KC4").unwrap(), "e0f0", "C-.4", "後炮平四")]
+    #[case(Board::from_fen("3k5/9/9/4P4/4P4/4P4/9/9/9/5K3").unwrap(), "e5d5", "2P.6", "中兵平六")]
+    fn notation(#[case] board: Board, #[case] mv: &str, #[case] wxf: &str, #[case] chinese: &str) {
+        let mv = mv.parse::<Move>().unwrap();
+        let red = board[mv.from].unwrap().is_red();
+
+        assert_eq!(super::wxf(&board, mv), wxf);
+        assert_eq!(super::chinese(&board, mv), chinese);
+        assert_eq!(parse(&board, red, wxf), Some(mv));
+        assert_eq!(parse(&board, red, chinese), Some(mv));
+    }
+
+    #[rstest]
+    #[case("炮二平五", "h2e2")]
+    #[case("马8进7", "h9g7")]
+    #[case("c2=5", "h2e2")]
+    #[case("N2+3", "h0g2")]
+    fn alternative_spelling(#[case] text: &str, #[case] mv: &str) {
+        let board = Board::opening();
+        let mv = mv.parse::<Move>().unwrap();
+        assert_eq!(parse(&board, board[mv.from].unwrap().is_red(), text), Some(mv));
+    }
+
+    #[rstest]
+    #[case("h2-e2", Ok("h2e2"))]
+    #[case("h2e2", Ok("h2e2"))]
+    #[case("H2-E2", Ok("h2e2"))]
+    #[case("h2e", Err(NotationError::Malformed("h2e".to_owned())))]
+    #[case("h2-e2-", Err(NotationError::Malformed("h2-e2-".to_owned())))]
+    #[case("j2e2", Err(NotationError::Malformed("j2e2".to_owned())))]
+    #[case("e5e6", Err(NotationError::Empty("e5".parse().unwrap())))]
+    #[case("h7e7", Err(NotationError::Opponent("h7".parse().unwrap())))]
+    #[case("h2h8", Err(NotationError::Illegal("h2h8".parse().unwrap())))]
+    fn iccs(#[case] text: &str, #[case] expected: Result<&str, NotationError>) {
+        let game = Game::opening();
+        let mv = parse_iccs(text).and_then(|mv| validate(&game, mv));
+        assert_eq!(mv, expected.map(|mv| mv.parse().unwrap()));
+
+        if let Ok(mv) = mv {
+            assert_eq!(super::iccs(mv), "h2-e2");
+        }
+    }
+
+    #[test]
+    fn unknown_move() {
+        assert_eq!(parse(&Board::opening(), true, "C2+5"), None);
+        assert_eq!(parse(&Board::opening(), false, "C2.9"), None);
+    }
+}
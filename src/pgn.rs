@@ -0,0 +1,429 @@
+use crate::game::Game;
+use crate::notation::{self, NotationError};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// a game in the xiangqi flavor of pgn, with moves written in iccs coordinates such as h2-e2 while games in wxf or
+// chinese notation as chosen by the format header can be read too
+#[derive(Clone)]
+pub struct Pgn {
+    headers: Vec<(String, String)>,
+    game: Game,
+    comments: Vec<Option<String>>, // written after the move of the same ply
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PgnError {
+    Header(String),                            // a tag pair that is not of the form [Key "value"]
+    Fen(String),                               // a start position that cannot be parsed
+    Move { ply: usize, error: NotationError }, // a move that is malformed or illegal at that point of the game
+    Empty,                                     // no game found in the text
+}
+
+impl Pgn {
+    const LINE_WIDTH: usize = 80;
+
+    pub fn new(game: Game) -> Self {
+        let headers = [
+            ("Game", "Chinese Chess"),
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("Red", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ];
+
+        Self {
+            headers: headers.map(|(key, value)| (key.to_owned(), value.to_owned())).to_vec(),
+            game,
+            comments: Vec::new(),
+        }
+    }
+
+    // parses every game in a file, such as one that games were appended to
+    pub fn parse_all(text: &str) -> Result<Vec<Self>, PgnError> {
+        let mut games = Vec::new();
+        let mut headers = Vec::new();
+        let mut moves = Vec::new();
+        let mut comments = Vec::new();
+
+        for token in Tokens(text.chars().peekable()) {
+            match token {
+                Token::Header(header) => {
+                    if !moves.is_empty() {
+                        games.push(Self::build(&mut headers, &mut moves, &mut comments)?);
+                    }
+
+                    headers.push(parse_header(&header)?);
+                }
+                Token::Result(result) => {
+                    set_header(&mut headers, "Result", result);
+                    games.push(Self::build(&mut headers, &mut moves, &mut comments)?);
+                }
+                Token::Move(text) => moves.push(text),
+                // comments before the first move are about the whole game and are dropped
+                Token::Comment(text) => {
+                    if let Some(ply) = moves.len().checked_sub(1) {
+                        comments.push((ply, text));
+                    }
+                }
+            }
+        }
+
+        if !headers.is_empty() || !moves.is_empty() {
+            games.push(Self::build(&mut headers, &mut moves, &mut comments)?);
+        }
+
+        Ok(games)
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        let (_, value) = self.headers.iter().find(|(k, _)| k == key)?;
+        Some(value)
+    }
+
+    pub fn set_header(&mut self, key: &str, value: impl Into<String>) {
+        set_header(&mut self.headers, key, value.into());
+    }
+
+    pub fn comment(&self, ply: usize) -> Option<&str> {
+        self.comments.get(ply)?.as_deref()
+    }
+
+    // the closing brace cannot be escaped, so it is removed from the text
+    pub fn set_comment(&mut self, ply: usize, text: &str) {
+        if self.comments.len() <= ply {
+            self.comments.resize(ply + 1, None);
+        }
+        self.comments[ply] = Some(text.replace('}', "").trim().to_owned());
+    }
+
+    // the game before any of its moves were made
+    pub fn start(&self) -> Game {
+        let mut start = self.game.clone();
+        while !start.history().is_empty() {
+            start.undo_move();
+        }
+        start
+    }
+
+    // takes the parts of a game that were parsed, leaving them empty for the next one
+    fn build(
+        headers: &mut Vec<(String, String)>,
+        moves: &mut Vec<String>,
+        comments: &mut Vec<(usize, String)>,
+    ) -> Result<Self, PgnError> {
+        let fen = headers
+            .iter()
+            .find(|(key, _)| key == "FEN")
+            .map(|(_, value)| value.as_str());
+        let mut game = match fen {
+            Some(fen) => notation::parse_fen(fen).ok_or_else(|| PgnError::Fen(fen.to_owned()))?,
+            None => Game::opening(),
+        };
+
+        // without a format header, moves that are not in iccs are read as wxf or chinese
+        let format = headers
+            .iter()
+            .find(|(key, _)| key == "Format")
+            .map(|(_, value)| value.to_ascii_lowercase());
+        let iccs = format.as_deref().is_none_or(|format| format == "iccs");
+        let describe = format.as_deref().is_none_or(|format| format != "iccs");
+
+        for (ply, text) in moves.drain(..).enumerate() {
+            let parsed = match notation::parse_iccs(&text) {
+                Ok(mv) if iccs => Ok(mv),
+                Err(error) if !describe => Err(error),
+                _ => notation::parse(game.board(), game.red_turn(), &text)
+                    .ok_or_else(|| NotationError::Malformed(text.clone())),
+            };
+            let mv = parsed
+                .and_then(|mv| notation::validate(&game, mv))
+                .map_err(|error| PgnError::Move { ply, error })?;
+            game.make_move(mv);
+        }
+
+        let mut pgn = Self::new(game);
+        for (key, value) in headers.drain(..) {
+            if key != "FEN" && key != "Format" {
+                pgn.set_header(&key, value);
+            }
+        }
+        for (ply, text) in comments.drain(..) {
+            pgn.set_comment(ply, &text);
+        }
+        Ok(pgn)
+    }
+}
+
+impl Display for Pgn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let start = self.start();
+        let escape = |value: &str| value.replace('\\', "\\\\").replace('"', "\\\"");
+
+        for (key, value) in &self.headers {
+            writeln!(f, "[{key} \"{}\"]", escape(value))?;
+        }
+
+        writeln!(f, "[FEN \"{}\"]", notation::fen(&start))?;
+        writeln!(f, "[Format \"ICCS\"]")?;
+        writeln!(f)?;
+
+        // black to move first is written as 1... so the move numbers still line up
+        let offset = if start.red_turn() { 0 } else { 1 };
+        let mut tokens = Vec::new();
+
+        for (index, &(mv, _)) in self.game.history().iter().enumerate() {
+            let ply = index + offset;
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            } else if index == 0 {
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+
+            tokens.push(notation::iccs(mv));
+            if let Some(comment) = self.comment(index) {
+                tokens.push(format!("{{{comment}}}"));
+            }
+        }
+
+        tokens.push(self.header("Result").unwrap_or("*").to_owned());
+
+        let mut width = 0;
+        for token in tokens {
+            if width > 0 && width + token.len() + 1 > Self::LINE_WIDTH {
+                writeln!(f)?;
+                width = 0;
+            } else if width > 0 {
+                write!(f, " ")?;
+                width += 1;
+            }
+
+            write!(f, "{token}")?;
+            width += token.len();
+        }
+
+        writeln!(f)
+    }
+}
+
+impl FromStr for Pgn {
+    type Err = PgnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_all(s)?.into_iter().next().ok_or(PgnError::Empty)
+    }
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::Header(header) => write!(f, "invalid pgn header '{header}'"),
+            PgnError::Fen(fen) => write!(f, "invalid pgn start position '{fen}'"),
+            PgnError::Move { ply, error } => write!(f, "invalid pgn move at ply {}: {error}", ply + 1),
+            PgnError::Empty => write!(f, "no pgn game found"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+enum Token {
+    Header(String),
+    Result(String),
+    Move(String),
+    Comment(String),
+}
+
+struct Tokens<I: Iterator<Item = char>>(std::iter::Peekable<I>);
+
+impl<I: Iterator<Item = char>> Iterator for Tokens<I> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let chars = &mut self.0;
+
+        loop {
+            match chars.next()? {
+                '[' => {
+                    let mut header = String::new();
+                    let mut escaped = false;
+                    let mut quoted = false;
+
+                    for current in chars.by_ref() {
+                        match current {
+                            ']' if !quoted => break,
+                            '"' if !escaped => quoted = !quoted,
+                            _ => {}
+                        }
+
+                        escaped = current == '\\' && !escaped;
+                        header.push(current);
+                    }
+
+                    return Some(Token::Header(header));
+                }
+                '{' => {
+                    let comment = chars.by_ref().take_while(|&current| current != '}').collect::<String>();
+                    return Some(Token::Comment(comment.trim().to_owned()));
+                }
+                ';' | '%' => while chars.next().is_some_and(|current| current != '\n') {},
+                '(' => {
+                    // variations are skipped, including nested ones
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match chars.next()? {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+                current if current.is_whitespace() => {}
+                current => {
+                    let mut token = current.to_string();
+                    while let Some(&current) = chars.peek()
+                        && !current.is_whitespace()
+                        && !"[]{};()".contains(current)
+                    {
+                        token.push(current);
+                        chars.next();
+                    }
+
+                    if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+                        return Some(Token::Result(token));
+                    }
+
+                    // move numbers such as 12. or 12... may be attached to the move itself, while wxf moves such as
+                    // C2.5 contain a dot of their own
+                    let number = token.find(|current: char| !current.is_ascii_digit());
+                    let token = match number {
+                        Some(end) if end > 0 && token[end..].starts_with('.') => token[end..].trim_start_matches('.'),
+                        None => "",
+                        _ => token.as_str(),
+                    };
+                    let token = token.trim_end_matches(['+', '#', '!', '?']);
+                    if !token.is_empty() && !token.starts_with('$') {
+                        return Some(Token::Move(token.to_owned()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_header(header: &str) -> Result<(String, String), PgnError> {
+    let invalid = || PgnError::Header(header.to_owned());
+    let (key, value) = header.trim().split_once(char::is_whitespace).ok_or_else(invalid)?;
+    let value = value.trim().strip_prefix('"').and_then(|value| value.strip_suffix('"'));
+
+    let mut unescaped = String::new();
+    let mut chars = value.ok_or_else(invalid)?.chars();
+    while let Some(current) = chars.next() {
+        unescaped.push(if current == '\\' {
+            chars.next().ok_or_else(invalid)?
+        } else {
+            current
+        });
+    }
+
+    Ok((key.to_owned(), unescaped))
+}
+
+fn set_header(headers: &mut Vec<(String, String)>, key: &str, value: String) {
+    match headers.iter_mut().find(|(k, _)| k == key) {
+        Some((_, existing)) => *existing = value,
+        None => headers.push((key.to_owned(), value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn round_trip() {
+        let mut game = Game::opening();
+        for mv in ["h2e2", "h9g7", "h0g2", "i9h9"] {
+            game.make_move(mv.parse().unwrap());
+        }
+
+        let mut pgn = Pgn::new(game);
+        pgn.set_header("Red", "a \"quoted\" name");
+        pgn.set_header("Result", "1/2-1/2");
+        pgn.set_comment(1, "depth 3 score -20");
+
+        let text = pgn.to_string();
+        assert!(text.contains("1. h2-e2 h9-g7 {depth 3 score -20} 2. h0-g2 i9-h9 1/2-1/2"));
+
+        let parsed = text.parse::<Pgn>().unwrap();
+        assert_eq!(parsed.to_string(), text);
+        assert_eq!(parsed.header("Red"), Some("a \"quoted\" name"));
+        assert_eq!(parsed.comment(1), Some("depth 3 score -20"));
+        assert_eq!(parsed.comment(0), None);
+        assert_eq!(parsed.game().board().fen(), pgn.game().board().fen());
+    }
+
+    #[test]
+    fn foreign_game() {
+        let text = "[Game \"Chinese Chess\"]\n\
+            [FEN \"rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR b - - 0 1\"]\n\
+            {a comment} 1... h9-g7 (1... h7e7) 2.h2e2 $1 b9c7 ; trailing comment\n\
+            0-1\n\
+            [Event \"second\"]\n\
+            1. c3c4 *";
+
+        let games = Pgn::parse_all(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].header("Result"), Some("0-1"));
+        assert_eq!(games[0].game().history().len(), 3);
+        assert!(games[0].start().board().fen().starts_with("rheakaehr"));
+        assert!(!games[0].start().red_turn());
+        assert_eq!(games[1].header("Event"), Some("second"));
+        assert_eq!(games[1].game().history().len(), 1);
+    }
+
+    #[test]
+    fn wxf_game() {
+        let text = "[Game \"Chinese Chess\"]\n\
+            [Format \"WXF\"]\n\
+            1. C2.5 H8+7 2.H2+3 R9.8 3. R1.2 c8.9 *\n\
+            [Format \"Chinese\"]\n\
+            1. 炮二平五 马8进7 *";
+
+        let games = Pgn::parse_all(text).unwrap();
+        let moves = |pgn: &Pgn| {
+            pgn.game()
+                .history()
+                .iter()
+                .map(|&(mv, _)| notation::iccs(mv))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(moves(&games[0]), ["h2-e2", "h9-g7", "h0-g2", "i9-h9", "i0-h0", "h7-i7"]);
+        assert_eq!(moves(&games[1]), ["h2-e2", "h9-g7"]);
+        assert_eq!(games[0].header("Format"), None);
+        assert!(games[0].to_string().contains("[Format \"ICCS\"]"));
+
+        // iccs moves are not accepted once the format says otherwise
+        let error = "[Format \"WXF\"] 1. h2e2 *".parse::<Pgn>().err();
+        let error = error.map(|error| matches!(error, PgnError::Move { ply: 0, .. }));
+        assert_eq!(error, Some(true));
+    }
+
+    #[rstest]
+    #[case("1. h2e3", PgnError::Move { ply: 0, error: NotationError::Illegal("h2e3".parse().unwrap()) })]
+    #[case("1. h2e2 zz", PgnError::Move { ply: 1, error: NotationError::Malformed("zz".to_owned()) })]
+    #[case("[FEN \"xyz\"] *", PgnError::Fen("xyz".to_owned()))]
+    #[case("[Event] *", PgnError::Header("Event".to_owned()))]
+    #[case("", PgnError::Empty)]
+    fn invalid(#[case] text: &str, #[case] error: PgnError) {
+        assert_eq!(text.parse::<Pgn>().err(), Some(error));
+    }
+}
@@ -1,8 +1,12 @@
 use crate::display_format::DisplayFormat;
 use crate::game::Game;
 use crate::location::Move;
+use crate::notation;
 use std::fmt::{Display, Formatter};
 use std::ops::Neg;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 pub struct Ranker {
     game: Game,
@@ -12,6 +16,10 @@ pub struct Ranker {
     evaluated: u32,
     pruned: u32,
     moves_buffer: Vec<Move>,
+    stop: Option<Arc<AtomicBool>>, // searches give up midway once this is set, possibly from another thread
+    deadline: Option<Instant>,     // or once this passes
+    aborted: bool,
+    visited: u32, // interior nodes searched, so that the clock is only read every so often
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -29,6 +37,10 @@ impl Ranker {
             evaluated: 0,
             pruned: 0,
             moves_buffer: Vec::new(),
+            stop: None,
+            deadline: None,
+            aborted: false,
+            visited: 0,
         }
     }
 
@@ -44,6 +56,43 @@ impl Ranker {
         self.best_chain.last().copied()
     }
 
+    // number of positions evaluated since the last move was made
+    pub fn evaluated(&self) -> u32 {
+        self.evaluated
+    }
+
+    pub fn score(&self) -> i32 {
+        self.best_rank.data
+    }
+
+    pub fn variation(&self) -> impl Iterator<Item = Move> {
+        // the chain is stored in reverse order of play
+        self.best_chain.iter().rev().copied()
+    }
+
+    pub fn set_stop(&mut self, stop: Option<Arc<AtomicBool>>) {
+        self.stop = stop;
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // same as stopped but cheap enough to check at every node, reading the clock only once in a while
+    fn interrupted(&mut self) -> bool {
+        const CLOCK_INTERVAL: u32 = 64;
+
+        self.visited = self.visited.wrapping_add(1);
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || self.visited.is_multiple_of(CLOCK_INTERVAL)
+                && self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
     pub fn make_move(&mut self, mv: Move) {
         self.game.make_move(mv);
         self.max_depth = 0;
@@ -53,16 +102,23 @@ impl Ranker {
         self.pruned = 0;
     }
 
+    // a search that is stopped midway is thrown away, keeping the result of the previous depth
     pub fn deeper(&mut self) {
         self.max_depth += 1;
+        self.aborted = false;
 
         let upper = Rank::mate(0);
         let lower = -upper;
 
-        let chain = std::mem::take(&mut self.best_chain);
+        let chain = self.best_chain.clone();
         let (rank, chain) = self.search_hinted(0, lower, upper, chain);
         assert!(self.moves_buffer.is_empty());
 
+        if self.aborted {
+            self.max_depth -= 1;
+            return;
+        }
+
         self.best_rank = rank;
         self.best_chain = chain;
     }
@@ -108,6 +164,12 @@ impl Ranker {
             return (Rank::new(self.game.evaluate()), Vec::new());
         }
 
+        // the root and leaves are still searched so that the first depth always completes
+        if depth > 0 && (self.aborted || self.interrupted()) {
+            self.aborted = true;
+            return (Rank::new(0), Vec::new());
+        }
+
         let old_length = self.moves_buffer.len();
         self.game.fill_moves(&mut self.moves_buffer);
 
@@ -173,13 +235,12 @@ impl Ranker {
                 };
 
                 let mut game = ranker.game.clone();
-                let piece = game[best.from].unwrap();
 
                 write!(
                     f,
                     "{} {} = {} / {}",
                     best,
-                    piece.display(format.with_concise(true)),
+                    notation::display(game.board(), best, format),
                     ranker.best_rank,
                     ranker.best_chain.len(),
                 )?;
@@ -196,8 +257,7 @@ impl Ranker {
                         _ => write!(f, ", ")?,
                     }
 
-                    let piece = game[mv.from].unwrap();
-                    write!(f, "{} {}", mv, piece.display(format.with_concise(true)))?;
+                    write!(f, "{} {}", mv, notation::display(game.board(), mv, format))?;
 
                     game.make_move(mv);
                 }